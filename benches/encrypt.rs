@@ -1,5 +1,6 @@
 #![cfg(feature = "nightly")]
 #![feature(test)]
+#![allow(clippy::redundant_static_lifetimes)]
 
 extern crate test;
 extern crate delta_l as dl;
@@ -50,3 +51,9 @@ fn decrypt(b: &mut test::Bencher){
 fn decrypt_no_checksum(b: &mut test::Bencher){
     b.iter(|| test::black_box(dl::decode(def(), &mut TEST_DATA_DELTA_NOC, &mut SeekableSink::default())).unwrap());
 }
+
+#[bench]
+fn decrypt_in_place(b: &mut test::Bencher){
+    let mut buf = TEST_DATA_DELTA_NOC[4..].to_vec();
+    b.iter(|| dl::decode_in_place(test::black_box(&mut buf), &mut def(), &mut 0));
+}
@@ -0,0 +1,363 @@
+//! Delta encodings of higher orders, of words wider than a byte, of interleaved channels and chained with XOR
+
+use byteorder::{LittleEndian, BigEndian, ByteOrder};
+
+use crate::{Offset, ZeroOffset, encode_in_place, decode_in_place, simd};
+
+/// The highest order a [`Delta`] can have
+pub const MAX_ORDER: u8 = 4;
+
+/// The highest stride a [`Delta`] can have
+pub const MAX_STRIDE: u8 = 16;
+
+const LASTS_LEN: usize = MAX_ORDER as usize * MAX_STRIDE as usize;
+
+/// The integers that are chained together by a [`Delta`] encoding
+///
+/// Words wider than a byte are added with carries between their bytes, which suits data like 16-bit audio samples.
+/// The offsets are still added to each byte on their own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Word {
+    /// Single bytes, as in the plain encoding
+    U8,
+    /// `u16`s in little endian
+    U16Le,
+    /// `u16`s in big endian
+    U16Be,
+    /// `u32`s in little endian
+    U32Le,
+    /// `u32`s in big endian
+    U32Be,
+    /// `u64`s in little endian
+    U64Le,
+    /// `u64`s in big endian
+    U64Be,
+}
+
+impl Word {
+    /// The number of bytes in a word
+    #[inline]
+    pub fn size(self) -> usize {
+        match self {
+            Word::U8 => 1,
+            Word::U16Le | Word::U16Be => 2,
+            Word::U32Le | Word::U32Be => 4,
+            Word::U64Le | Word::U64Be => 8,
+        }
+    }
+    /// Whether the most significant byte comes first
+    #[inline]
+    pub fn is_big_endian(self) -> bool {
+        matches!(self, Word::U16Be | Word::U32Be | Word::U64Be)
+    }
+    /// The word of the given size and byte order, if there is one
+    pub(crate) fn from_parts(size: u8, big_endian: bool) -> Option<Self> {
+        Some(match (size, big_endian) {
+            (1, _) => Word::U8,
+            (2, false) => Word::U16Le,
+            (2, true) => Word::U16Be,
+            (4, false) => Word::U32Le,
+            (4, true) => Word::U32Be,
+            (8, false) => Word::U64Le,
+            (8, true) => Word::U64Be,
+            _ => return None,
+        })
+    }
+    #[inline]
+    fn read(self, bytes: &[u8]) -> u64 {
+        if self.is_big_endian() {
+            BigEndian::read_uint(bytes, bytes.len())
+        } else {
+            LittleEndian::read_uint(bytes, bytes.len())
+        }
+    }
+    /// Writes the low bytes of `n` that fit in the word
+    #[inline]
+    fn write(self, bytes: &mut [u8], n: u64) {
+        let n = n & u64::MAX >> (64 - 8 * bytes.len());
+        if self.is_big_endian() {
+            BigEndian::write_uint(bytes, n, bytes.len())
+        } else {
+            LittleEndian::write_uint(bytes, n, bytes.len())
+        }
+    }
+}
+
+/// How the words are combined with the words they're chained to and with the offsets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Combine {
+    /// Wrapping addition, as in the plain encoding
+    Add,
+    /// Exclusive or, so a byte is encoded as `p ^ offset ^ prev`, as some older formats do
+    Xor,
+}
+
+/// The operation of a [`Combine`], so the loops can be made for each of them
+trait Operation {
+    fn combine(a: u64, b: u64) -> u64;
+    /// Undoes `combine`, so `separate(combine(a, b), b) == a`
+    fn separate(c: u64, b: u64) -> u64;
+    /// Separates the next offsets from the bytes of `buf`
+    fn separate_offsets<O: Offset>(buf: &mut [u8], offsetter: &mut O);
+}
+
+struct AddOp;
+struct XorOp;
+
+impl Operation for AddOp {
+    #[inline]
+    fn combine(a: u64, b: u64) -> u64 {
+        a.wrapping_add(b)
+    }
+    #[inline]
+    fn separate(c: u64, b: u64) -> u64 {
+        c.wrapping_sub(b)
+    }
+    #[inline]
+    fn separate_offsets<O: Offset>(buf: &mut [u8], offsetter: &mut O) {
+        offsetter.sub_offsets(buf)
+    }
+}
+
+impl Operation for XorOp {
+    #[inline]
+    fn combine(a: u64, b: u64) -> u64 {
+        a ^ b
+    }
+    #[inline]
+    fn separate(c: u64, b: u64) -> u64 {
+        c ^ b
+    }
+    #[inline]
+    fn separate_offsets<O: Offset>(buf: &mut [u8], offsetter: &mut O) {
+        for b in buf {
+            *b ^= offsetter.next_offset();
+        }
+    }
+}
+
+/// How each byte is chained to the ones before it, and the bytes that are remembered for that
+///
+/// Order 1 is the plain Delta-L encoding, where the byte before is added to each byte.
+/// Order `n` repeats that step `n` times, each time with its own previous byte, before the offset is added,
+/// which spreads out data that changes slowly, like samples, better.
+///
+/// With a [`Word`] wider than a byte, the steps add whole words instead.
+/// Bytes at the end of the stream that don't make up a whole word are encoded a byte at a time,
+/// as at the start of a stream.
+/// So buffers given to [`encode`](Delta::encode) and [`decode`](Delta::decode)
+/// have to be whole words, except for the last one of the stream.
+///
+/// With a stride of `n`, the words are `n` interleaved channels, like the colours of pixels or stereo samples,
+/// and each word is chained to the word of the same channel `n` words before it.
+///
+/// All of the steps, and adding the offset, can be done with XOR instead, see [`Combine`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Delta {
+    order: u8,
+    word: Word,
+    stride: u8,
+    combine: Combine,
+    /// The channel of the next word
+    channel: u8,
+    /// The word before in each step, [`MAX_ORDER`] for each channel
+    lasts: [u64; LASTS_LEN],
+}
+
+impl Default for Delta {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Delta {
+    /// The plain Delta-L encoding of order 1
+    #[inline]
+    pub const fn new() -> Self {
+        Delta {
+            order: 1,
+            word: Word::U8,
+            stride: 1,
+            combine: Combine::Add,
+            channel: 0,
+            lasts: [0; LASTS_LEN],
+        }
+    }
+    /// A delta encoding of the given order
+    ///
+    /// # Panics
+    ///
+    /// Panics if `order` is 0 or more than [`MAX_ORDER`].
+    pub fn with_order(order: u8) -> Self {
+        assert!((1..=MAX_ORDER).contains(&order), "the order has to be from 1 to {}", MAX_ORDER);
+        Delta {
+            order,
+            ..Self::new()
+        }
+    }
+    /// Changes the words that are chained together
+    #[inline]
+    pub fn with_word(self, word: Word) -> Self {
+        Delta {
+            word,
+            ..self
+        }
+    }
+    /// Changes the number of interleaved channels
+    ///
+    /// # Panics
+    ///
+    /// Panics if `stride` is 0 or more than [`MAX_STRIDE`].
+    pub fn with_stride(self, stride: u8) -> Self {
+        assert!((1..=MAX_STRIDE).contains(&stride), "the stride has to be from 1 to {}", MAX_STRIDE);
+        Delta {
+            stride,
+            ..self
+        }
+    }
+    /// Changes how the words are combined
+    #[inline]
+    pub fn with_combine(self, combine: Combine) -> Self {
+        Delta {
+            combine,
+            ..self
+        }
+    }
+    /// The order of the encoding
+    #[inline]
+    pub fn order(&self) -> u8 {
+        self.order
+    }
+    /// The words that are chained together
+    #[inline]
+    pub fn word(&self) -> Word {
+        self.word
+    }
+    /// The number of interleaved channels
+    #[inline]
+    pub fn stride(&self) -> u8 {
+        self.stride
+    }
+    /// How the words are combined
+    #[inline]
+    pub fn combine(&self) -> Combine {
+        self.combine
+    }
+    /// Forgets the bytes before, as at the start of a stream
+    #[inline]
+    pub fn reset(&mut self) {
+        self.channel = 0;
+        self.lasts = [0; LASTS_LEN];
+    }
+    /// Whether this encodes the same as [`Delta::new`], ignoring the remembered bytes
+    #[inline]
+    pub(crate) fn is_plain(&self) -> bool {
+        self.order == 1 && self.word == Word::U8 && self.stride == 1 && self.combine == Combine::Add
+    }
+    /// Whether each byte is added to the byte right before it, which has faster loops
+    #[inline]
+    fn is_bytewise(&self) -> bool {
+        self.word == Word::U8 && self.stride == 1 && self.combine == Combine::Add
+    }
+    /// The encoding of bytes that don't make up a whole word
+    #[inline]
+    fn for_tail(&self) -> Self {
+        Self::with_order(self.order).with_combine(self.combine)
+    }
+    /// Delta encodes `buf` in place, continuing from the bytes before it
+    pub fn encode<O: Offset>(&mut self, buf: &mut [u8], offsetter: &mut O) {
+        match self.combine {
+            _ if self.is_bytewise() => self.encode_bytes(buf, offsetter),
+            Combine::Add => self.encode_words::<AddOp, _>(buf, offsetter),
+            Combine::Xor => self.encode_words::<XorOp, _>(buf, offsetter),
+        }
+    }
+    fn encode_bytes<O: Offset>(&mut self, buf: &mut [u8], offsetter: &mut O) {
+        if self.order == 1 {
+            let mut last = self.lasts[0] as u8;
+            encode_in_place(buf, offsetter, &mut last);
+            self.lasts[0] = last.into();
+            return
+        }
+
+        let lasts = &mut self.lasts[..self.order as usize];
+        for b in buf {
+            let mut x = *b;
+            for last in lasts.iter_mut() {
+                let step = x;
+                x = x.wrapping_add(*last as u8);
+                *last = step.into();
+            }
+            *b = x.wrapping_add(offsetter.next_offset());
+        }
+    }
+    fn encode_words<C: Operation, O: Offset>(&mut self, buf: &mut [u8], offsetter: &mut O) {
+        let word = self.word;
+        let mut channel = self.channel as usize;
+        let mut words = buf.chunks_exact_mut(word.size());
+        for bytes in &mut words {
+            let mut x = word.read(bytes);
+            for last in &mut self.lasts[channel * MAX_ORDER as usize..][..self.order as usize] {
+                let step = x;
+                x = C::combine(x, *last);
+                *last = step;
+            }
+            word.write(bytes, x);
+            for b in bytes {
+                *b = C::combine((*b).into(), offsetter.next_offset().into()) as u8;
+            }
+            channel = (channel + 1) % self.stride as usize;
+        }
+        self.channel = channel as u8;
+        let tail = words.into_remainder();
+        if !tail.is_empty() {
+            self.for_tail().encode(tail, offsetter);
+        }
+    }
+    /// Delta decodes `buf` in place, continuing from the bytes before it
+    pub fn decode<O: Offset>(&mut self, buf: &mut [u8], offsetter: &mut O) {
+        match self.combine {
+            _ if self.is_bytewise() => self.decode_bytes(buf, offsetter),
+            Combine::Add => self.decode_words::<AddOp, _>(buf, offsetter),
+            Combine::Xor => self.decode_words::<XorOp, _>(buf, offsetter),
+        }
+    }
+    fn decode_bytes<O: Offset>(&mut self, buf: &mut [u8], offsetter: &mut O) {
+        if self.order == 1 {
+            let mut last = self.lasts[0] as u8;
+            decode_in_place(buf, offsetter, &mut last);
+            self.lasts[0] = last.into();
+            return
+        }
+
+        offsetter.sub_offsets(buf);
+        // The steps are undone last to first, each over the whole buffer
+        for last in self.lasts[..self.order as usize].iter_mut().rev() {
+            let mut byte = *last as u8;
+            simd::undelta(buf, &mut byte);
+            *last = byte.into();
+        }
+    }
+    fn decode_words<C: Operation, O: Offset>(&mut self, buf: &mut [u8], offsetter: &mut O) {
+        C::separate_offsets(buf, offsetter);
+
+        let word = self.word;
+        let mut channel = self.channel as usize;
+        let mut words = buf.chunks_exact_mut(word.size());
+        for bytes in &mut words {
+            let mut x = word.read(bytes);
+            for last in self.lasts[channel * MAX_ORDER as usize..][..self.order as usize].iter_mut().rev() {
+                x = C::separate(x, *last);
+                *last = x;
+            }
+            word.write(bytes, x);
+            channel = (channel + 1) % self.stride as usize;
+        }
+        self.channel = channel as u8;
+        let tail = words.into_remainder();
+        if !tail.is_empty() {
+            self.for_tail().decode(tail, &mut ZeroOffset);
+        }
+    }
+}
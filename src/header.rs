@@ -4,13 +4,19 @@
 
 use byteorder::{LittleEndian, ByteOrder};
 
-use self::Error::{Io, InvalidHeader, ChecksumMismatch};
+use self::Error::{Io, InvalidHeader, ChecksumMismatch, PassphraseRequired, Unsupported, AuthenticationFailed, WrongPassphrase, Truncated, TrailingData};
 
 use std::fmt;
 use std::io::{self, Read, Write, Seek, SeekFrom};
 use std::error::Error as ErrorTrait;
+use std::hash::{Hasher, BuildHasher};
+use std::collections::hash_map::RandomState;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::{Offset, DeltaWriter, DeltaReader};
+use siphasher::sip::SipHasher;
+use siphasher::sip128::{self, Hasher128, Hash128};
+
+use crate::{Offset, PassHashOffsetter, KeystreamOffsetter, DeltaWriter, DeltaReader, Delta, MAX_ROUNDS};
 
 /// Result alias for convenience
 pub type Result = std::result::Result<(), Error>;
@@ -24,11 +30,51 @@ pub enum Error{
     InvalidHeader,
     /// Checksum mismatch error
     ChecksumMismatch,
+    /// The header stores a salt for the passphrase, so it can't be decoded with just an offsetter
+    PassphraseRequired,
+    /// The header is of a newer version or uses features that aren't supported,
+    /// or the options need a key the offsetter doesn't have
+    Unsupported,
+    /// The authentication tag didn't match the header and ciphertext
+    AuthenticationFailed,
+    /// The key check value in the header didn't match the offsetter
+    WrongPassphrase,
+    /// The payload is shorter than the length stored in the header
+    Truncated {
+        /// The stored length
+        expected: u64,
+        /// The length of the payload
+        actual: u64,
+    },
+    /// The payload is longer than the length stored in the header
+    TrailingData,
 }
 
+mod armor;
+mod chunked;
+#[cfg(feature = "compress")]
+mod compress;
+mod delta;
 mod hashing_io;
+mod info;
+mod metadata;
+mod stream;
+mod trailer;
+mod v2;
 
+use self::chunked::ChunksRead;
 use self::hashing_io::{HashingRead, HashingWrite};
+use self::trailer::TrailerRead;
+use self::v2::{Header, Algorithm, FLAG_CHECKSUM, FLAG_AUTH, FLAG_TRAILER, FLAG_CHUNKED, FLAG_LENGTH, FLAG_COMPRESSED, FLAG_DELTA, EXT_KEY_CHECK};
+#[cfg(feature = "compress")]
+use self::compress::{CompressRead, DecompressWrite};
+
+pub use self::armor::{ArmorWriter, ArmorReader};
+pub use self::chunked::{encode_chunked, encode_chunked_with_passphrase, encode_parallel, encode_parallel_with_passphrase, decode_parallel, decode_parallel_with_passphrase, ChunkedReader};
+pub use self::info::{HeaderInfo, Variant, read_header};
+pub use self::metadata::Metadata;
+pub use self::stream::{Encoder, Decoder};
+pub use self::v2::FIRST_APPLICATION_TAG;
 
 impl fmt::Display for Error{
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
@@ -36,23 +82,51 @@ impl fmt::Display for Error{
             Io(ref err)      => err.fmt(f),
             InvalidHeader    => write!(f, "The header was not valid."),
             ChecksumMismatch => write!(f, "The checksum of the output file did not match the checksum in the header."),
+            PassphraseRequired => write!(f, "The header requires a passphrase to derive the offsets."),
+            Unsupported      => write!(f, "The header uses a version or features that aren't supported."),
+            AuthenticationFailed => write!(f, "The authentication tag did not match, so the file has been changed or the passphrase is wrong."),
+            WrongPassphrase  => write!(f, "The passphrase did not match the key check value in the header."),
+            Truncated{expected, actual} => write!(f, "The file was truncated to {} of {} bytes.", actual, expected),
+            TrailingData     => write!(f, "The file is longer than the length in the header."),
         }
     }
 }
 
 impl ErrorTrait for Error{
+    #[allow(deprecated)]
     fn description(&self) -> &str{
         match *self{
             Io(ref err)      => err.description(),
             InvalidHeader    => "header wasn't valid",
             ChecksumMismatch => "checksum of output file didn't match header checksum",
+            PassphraseRequired => "header requires a passphrase",
+            Unsupported      => "header isn't supported",
+            AuthenticationFailed => "authentication tag didn't match",
+            WrongPassphrase  => "passphrase didn't match the key check value",
+            Truncated{..}    => "file was truncated",
+            TrailingData     => "file is longer than the length in the header",
         }
     }
 }
 
+/// `io::Error`s wrapping an [`Error`] are unwrapped
 impl From<io::Error> for Error{
     fn from(e: io::Error) -> Error{
-        Error::Io(e)
+        if e.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            *e.into_inner().unwrap().downcast::<Error>().unwrap()
+        } else {
+            Error::Io(e)
+        }
+    }
+}
+
+/// Errors other than [`Error::Io`] become `InvalidData` errors wrapping them
+impl From<Error> for io::Error{
+    fn from(e: Error) -> io::Error{
+        match e{
+            Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
     }
 }
 
@@ -60,71 +134,877 @@ impl From<io::Error> for Error{
 pub fn encode_no_checksum<O: Offset, R: Read, W: Write>(offsetter: O, src: &mut R, dest: &mut W) -> Result{
     // Write header (Δl\n)
     dest.write_all(b"\xCE\x94l\n")?;
-    let mut dest = DeltaWriter::with_offsetter(dest, offsetter);
-
-    io::copy(src, &mut dest)?;
-    dest.flush().map_err(Into::into)
+    encode_plain(offsetter, Delta::new(), src, dest)
 }
 
 /// Encodes the `src` into `dest` using the checksum header
 pub fn encode_with_checksum<O: Offset, R: Read, W: Write + Seek>(offsetter: O, src: &mut R, dest: &mut W) -> Result{
     // Write header (ΔL\n)
     dest.write_all(b"\xCE\x94L\n")?;
-    dest.write_all(b"HASHCODE")?;
+    encode_checksummed(offsetter, src, dest)
+}
+
+/// Encodes the `src` into `dest` using the salted **no** checksum header
+///
+/// The offsets are derived using [`PassHashOffsetter::derive`] with a random salt,
+/// which is stored in the header along with `rounds`.
+/// Fails with [`Error::Unsupported`] if `rounds` is more than [`MAX_ROUNDS`](crate::MAX_ROUNDS).
+pub fn encode_salted_no_checksum<R: Read, W: Write>(passphrase: &str, rounds: u32, src: &mut R, dest: &mut W) -> Result{
+    check_rounds(rounds)?;
+    // Write header (Δk\n)
+    dest.write_all(b"\xCE\x94k\n")?;
+    let offsetter = write_salt(passphrase, rounds, dest)?;
+    encode_plain(offsetter, Delta::new(), src, dest)
+}
+
+/// Encodes the `src` into `dest` using the salted checksum header
+///
+/// The offsets are derived using [`PassHashOffsetter::derive`] with a random salt,
+/// which is stored in the header along with `rounds`.
+/// Fails with [`Error::Unsupported`] if `rounds` is more than [`MAX_ROUNDS`](crate::MAX_ROUNDS).
+pub fn encode_salted_with_checksum<R: Read, W: Write + Seek>(passphrase: &str, rounds: u32, src: &mut R, dest: &mut W) -> Result{
+    check_rounds(rounds)?;
+    // Write header (ΔK\n)
+    dest.write_all(b"\xCE\x94K\n")?;
+    let offsetter = write_salt(passphrase, rounds, dest)?;
+    encode_checksummed(offsetter, src, dest)
+}
+
+/// Options for encoding with the version 2 header
+///
+/// By default a checksum is stored.
+#[derive(Debug, Clone)]
+pub struct Options {
+    checksum: bool,
+    authenticate: bool,
+    length: bool,
+    compress: bool,
+    key_check: bool,
+    delta: Delta,
+    metadata: Option<Metadata>,
+    extensions: Vec<(u8, Vec<u8>)>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            checksum: true,
+            authenticate: false,
+            length: false,
+            compress: false,
+            key_check: false,
+            delta: Delta::new(),
+            metadata: None,
+            extensions: Vec::new(),
+        }
+    }
+}
+
+impl Options {
+    /// Returns the default options
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Sets whether to store a checksum of the plaintext
+    ///
+    /// It isn't keyed, so anyone can check a guess of the plaintext against it.
+    /// It's left out when an [authentication tag](Options::authenticate) is stored.
+    #[inline]
+    pub fn checksum(mut self, checksum: bool) -> Self {
+        self.checksum = checksum;
+        self
+    }
+    /// Sets whether to store an authentication tag
+    ///
+    /// The tag is a SipHash of the header and the ciphertext,
+    /// keyed by a key derived from [`Offset::key`].
+    /// Unlike the checksum, it can't be made or checked without knowing the key,
+    /// and it's checked before anything is decoded.
+    /// It replaces the checksum, which would give away whether a guess of the plaintext is right.
+    ///
+    /// Encoding fails with [`Error::Unsupported`] if the offsetter has no key,
+    /// like [`ZeroOffset`](crate::ZeroOffset) or a [`PassHashOffsetter`] made without a salt.
+    #[inline]
+    pub fn authenticate(mut self, authenticate: bool) -> Self {
+        self.authenticate = authenticate;
+        self
+    }
+    /// Sets whether to store the length of the plaintext
+    ///
+    /// Decoding a file that has been cut short then fails with [`Error::Truncated`],
+    /// and one with more data after it with [`Error::TrailingData`].
+    #[inline]
+    pub fn length(mut self, length: bool) -> Self {
+        self.length = length;
+        self
+    }
+    /// Sets whether to compress the plaintext before encoding it
+    ///
+    /// The chunked encoders fail with [`Error::Unsupported`] if this is set.
+    #[cfg(feature = "compress")]
+    #[inline]
+    pub fn compress(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+    /// Sets whether to store a short check value of the key
+    ///
+    /// Decoding with the wrong passphrase then fails with [`Error::WrongPassphrase`]
+    /// before anything is decoded. It's made from [`Offset::key`],
+    /// so nothing is stored for offsetters without a key.
+    #[inline]
+    pub fn key_check(mut self, key_check: bool) -> Self {
+        self.key_check = key_check;
+        self
+    }
+    /// Sets the delta encoding, which is stored in the header
+    ///
+    /// Only the plain encoding of order 1 can be decoded by versions that don't know about the others.
+    #[inline]
+    pub fn delta(mut self, delta: Delta) -> Self {
+        self.delta = delta;
+        self
+    }
+    /// Stores the metadata of the original file in the header
+    ///
+    /// It isn't encrypted, so anyone can read it with [`read_header`].
+    #[inline]
+    pub fn metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+    /// Makes the header these options describe for `offsetter`
+    fn header<O: Offset>(&self, offsetter: &O, algorithm: Algorithm, trailer: bool) -> Header {
+        let mut flags = 0;
+        if self.checksum && !self.authenticate {
+            flags |= FLAG_CHECKSUM;
+        }
+        if self.authenticate {
+            flags |= FLAG_AUTH;
+        }
+        if self.length {
+            flags |= FLAG_LENGTH;
+        }
+        if trailer && flags != 0 {
+            flags |= FLAG_TRAILER;
+        }
+        if self.compress {
+            flags |= FLAG_COMPRESSED;
+        }
+        if !self.delta.is_plain() {
+            flags |= FLAG_DELTA;
+        }
+
+        let mut extensions = Vec::with_capacity(self.extensions.len() + 1);
+        if self.key_check {
+            if let Some(check) = key_check(offsetter) {
+                extensions.push((EXT_KEY_CHECK, check.to_vec()));
+            }
+        }
+        extensions.extend(delta::to_extensions(&self.delta));
+        if let Some(ref metadata) = self.metadata {
+            extensions.extend(metadata.to_extensions());
+        }
+        extensions.extend_from_slice(&self.extensions);
+
+        Header {
+            algorithm,
+            flags,
+            extensions,
+        }
+    }
+    /// Adds an extension field for applications to store their own data in the header
+    ///
+    /// # Panics
+    /// If `tag` is below [`FIRST_APPLICATION_TAG`] or `data` is longer than `u16::MAX` bytes
+    pub fn extension(mut self, tag: u8, data: Vec<u8>) -> Self {
+        assert!(tag >= FIRST_APPLICATION_TAG, "extension tags below {} are reserved", FIRST_APPLICATION_TAG);
+        assert!(data.len() <= u16::MAX as usize, "extension data is too long");
+        self.extensions.push((tag, data));
+        self
+    }
+}
+
+/// How the offsets are derived from the passphrase in the version 2 header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kdf {
+    /// Uses [`PassHashOffsetter::derive`]
+    PassHash {
+        /// The number of rounds to stretch the passphrase with, at most [`MAX_ROUNDS`]
+        rounds: u32,
+    },
+    /// Uses [`KeystreamOffsetter::derive`]
+    Keystream {
+        /// The number of rounds to stretch the passphrase with, at most [`MAX_ROUNDS`]
+        rounds: u32,
+    },
+}
+
+/// Encodes the `src` into `dest` using the version 2 header
+///
+/// The offsetter isn't stored in the header, so the same one has to be given to [`decode`].
+pub fn encode_v2<O: Offset, R: Read, W: Write + Seek>(offsetter: O, options: &Options, src: &mut R, dest: &mut W) -> Result{
+    let header = options.header(&offsetter, Algorithm::Offsetter, false);
+    encode_v2_inner(offsetter, options.delta, &header, src, dest)
+}
+
+/// Encodes the `src` into `dest` using the version 2 header
+/// with offsets derived from the passphrase with a random salt
+///
+/// Decode it with [`decode_with_passphrase`].
+pub fn encode_v2_with_passphrase<R: Read, W: Write + Seek>(passphrase: &str, kdf: Kdf, options: &Options, src: &mut R, dest: &mut W) -> Result{
+    let (offsetter, algorithm) = derive_offsetter(passphrase, kdf)?;
+    let header = options.header(&offsetter, algorithm, false);
+    encode_v2_inner(offsetter, options.delta, &header, src, dest)
+}
+
+/// Like [`encode_v2`], but `dest` doesn't need to be seekable
+///
+/// The checksum and authentication tag are written in a trailer after the payload
+/// instead of in the header.
+pub fn encode_v2_streaming<O: Offset, R: Read, W: Write>(offsetter: O, options: &Options, src: &mut R, dest: &mut W) -> Result{
+    let header = options.header(&offsetter, Algorithm::Offsetter, true);
+    encode_v2_trailer(offsetter, options.delta, &header, src, dest)
+}
+
+/// Like [`encode_v2_with_passphrase`], but `dest` doesn't need to be seekable
+///
+/// The checksum and authentication tag are written in a trailer after the payload
+/// instead of in the header.
+pub fn encode_v2_streaming_with_passphrase<R: Read, W: Write>(passphrase: &str, kdf: Kdf, options: &Options, src: &mut R, dest: &mut W) -> Result{
+    let (offsetter, algorithm) = derive_offsetter(passphrase, kdf)?;
+    let header = options.header(&offsetter, algorithm, true);
+    encode_v2_trailer(offsetter, options.delta, &header, src, dest)
+}
+
+/// Derives an offsetter from the passphrase with a new salt
+fn derive_offsetter(passphrase: &str, kdf: Kdf) -> std::result::Result<(PassphraseOffsetter, Algorithm), Error> {
+    let salt = random_salt();
+    Ok(match kdf {
+        Kdf::PassHash{rounds} => {
+            check_rounds(rounds)?;
+            (PassphraseOffsetter::PassHash(PassHashOffsetter::derive(passphrase, &salt, rounds)), Algorithm::PassHash{salt, rounds})
+        }
+        Kdf::Keystream{rounds} => {
+            check_rounds(rounds)?;
+            (PassphraseOffsetter::Keystream(KeystreamOffsetter::derive(passphrase, &salt, rounds)), Algorithm::Keystream{salt, rounds})
+        }
+    })
+}
+
+/// Headers with more rounds than [`MAX_ROUNDS`] aren't decoded, so they aren't written either
+fn check_rounds(rounds: u32) -> Result {
+    if rounds > MAX_ROUNDS {
+        return Err(Unsupported)
+    }
+    Ok(())
+}
+
+fn encode_v2_inner<O: Offset, R: Read, W: Write + Seek>(offsetter: O, delta: Delta, header: &Header, src: &mut R, dest: &mut W) -> Result{
+    let head = header.to_bytes();
+    dest.write_all(&head)?;
+
+    #[cfg(feature = "compress")]
+    {
+        if header.has_flag(FLAG_COMPRESSED) {
+            return encode_v2_payload(offsetter, delta, header, &head, &mut CompressRead::new(src), dest)
+        }
+    }
+    encode_v2_payload(offsetter, delta, header, &head, src, dest)
+}
+
+fn encode_v2_payload<O: Offset, R: Read, W: Write + Seek>(offsetter: O, delta: Delta, header: &Header, head: &[u8], src: &mut R, dest: &mut W) -> Result{
+    if header.has_flag(FLAG_AUTH) {
+        let mac = auth_hasher(&offsetter, head)?;
+        encode_tagged(offsetter, delta, header.flags, Some(mac), src, dest)
+    } else if header.has_flag(FLAG_CHECKSUM | FLAG_LENGTH) {
+        encode_tagged(offsetter, delta, header.flags, None, src, dest)
+    } else {
+        encode_plain(offsetter, delta, src, dest)
+    }
+}
+
+fn encode_v2_trailer<O: Offset, R: Read, W: Write>(offsetter: O, delta: Delta, header: &Header, src: &mut R, dest: &mut W) -> Result{
+    let head = header.to_bytes();
+    dest.write_all(&head)?;
+
+    let mac = if header.has_flag(FLAG_AUTH) {
+        Some(auth_hasher(&offsetter, &head)?)
+    } else {
+        None
+    };
+    #[cfg(feature = "compress")]
+    let (len, hash, mac) = if header.has_flag(FLAG_COMPRESSED) {
+        encode_hashed(offsetter, delta, mac, &mut CompressRead::new(src), dest)?
+    } else {
+        encode_hashed(offsetter, delta, mac, src, dest)?
+    };
+    #[cfg(not(feature = "compress"))]
+    let (len, hash, mac) = encode_hashed(offsetter, delta, mac, src, dest)?;
+
+    dest.write_all(&tag_bytes(header.flags, len, hash, mac))?;
+    dest.flush().map_err(Into::into)
+}
+
+fn encode_plain<O: Offset, R: Read, W: Write>(offsetter: O, delta: Delta, src: &mut R, dest: &mut W) -> Result{
+    let mut dest = DeltaWriter::with_delta(dest, offsetter, delta);
+
+    io::copy(src, &mut dest)?;
+    dest.finish()?;
+    Ok(())
+}
+
+/// Writes the checksum placeholder, encodes `src` and fills in the checksum afterwards
+#[inline]
+fn encode_checksummed<O: Offset, R: Read, W: Write + Seek>(offsetter: O, src: &mut R, dest: &mut W) -> Result{
+    encode_tagged(offsetter, Delta::new(), FLAG_CHECKSUM, None, src, dest)
+}
+
+/// Writes placeholders for the length, the checksum and the authentication tag, if `flags` asks for them,
+/// encodes `src` and fills them in afterwards
+///
+/// `mac` should already have been given the header.
+fn encode_tagged<O: Offset, R: Read, W: Write + Seek>(offsetter: O, delta: Delta, flags: u16, mac: Option<SipHasher>, src: &mut R, dest: &mut W) -> Result{
+    let tags_pos = dest.stream_position()?;
+    if flags & FLAG_LENGTH != 0 {
+        dest.write_all(b"PLAINLEN")?;
+    }
+    if flags & FLAG_CHECKSUM != 0 {
+        dest.write_all(b"HASHCODE")?;
+    }
+    if mac.is_some() {
+        dest.write_all(b"AUTHCODE")?;
+    }
+
+    let (len, hash, mac) = encode_hashed(offsetter, delta, mac, src, dest)?;
+    let tags = tag_bytes(flags, len, hash, mac);
+
+    let end = dest.stream_position()?;
+    dest.seek(SeekFrom::Start(tags_pos))?;
+    dest.write_all(&tags)?;
+    dest.seek(SeekFrom::Start(end))?;
+
+    dest.flush().map_err(Into::into)
+}
 
+/// Encodes `src` into `dest` and returns the length and checksum of `src`,
+/// and `mac` after it has been given what was written
+fn encode_hashed<O: Offset, R: Read, W: Write>(offsetter: O, delta: Delta, mac: Option<SipHasher>, src: &mut R, dest: &mut W) -> io::Result<(u64, u64, Option<SipHasher>)> {
     let mut src = HashingRead::new(src);
-    let mut dest = DeltaWriter::with_offsetter(dest, offsetter);
-    io::copy(&mut src, &mut dest)?;
-    dest.flush()?;
+    let mac = match mac {
+        Some(mac) => {
+            let mut dest = DeltaWriter::with_delta(HashingWrite::with_hasher(dest, mac), offsetter, delta);
+            io::copy(&mut src, &mut dest)?;
+            Some(dest.finish()?.into_parts().1)
+        }
+        None => {
+            let mut dest = DeltaWriter::with_delta(dest, offsetter, delta);
+            io::copy(&mut src, &mut dest)?;
+            dest.finish()?;
+            None
+        }
+    };
+    let len = src.count();
     let (_, hash) = src.into_inner();
-    let dest = dest.into_inner();
 
-    let mut checksum = [0; 8];
-    LittleEndian::write_u64(&mut checksum, hash);
+    Ok((len, hash, mac))
+}
 
-    dest.seek(SeekFrom::Start(4))?;
-    dest.write_all(&checksum)?;
+/// Returns the length, checksum and authentication tag as they're stored, if `flags` asks for them
+fn tag_bytes(flags: u16, len: u64, checksum: u64, mac: Option<SipHasher>) -> Vec<u8> {
+    let len = if flags & FLAG_LENGTH != 0 { Some(len) } else { None };
+    let checksum = if flags & FLAG_CHECKSUM != 0 { Some(checksum) } else { None };
 
-    dest.flush().map_err(Into::into)
+    let mut tags = summary_bytes(len, checksum);
+    if let Some(mac) = mac {
+        tags.extend_from_slice(&u64_bytes(finish_mac(mac, len, checksum)));
+    }
+    tags
 }
 
-/// Decodes the `src` into `dest` determining whether to check checksum based on header
-pub fn decode<O: Offset, R: Read, W: Write>(offsetter: O, src: &mut R, dest: &mut W) -> Result {
+/// Returns the length and checksum as they're stored
+fn summary_bytes(len: Option<u64>, checksum: Option<u64>) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(16);
+    if let Some(len) = len {
+        bytes.extend_from_slice(&u64_bytes(len));
+    }
+    if let Some(checksum) = checksum {
+        bytes.extend_from_slice(&u64_bytes(checksum));
+    }
+    bytes
+}
+
+/// Gives the length and checksum to `mac`, which has been given the header and ciphertext, and returns the tag
+fn finish_mac(mut mac: SipHasher, len: Option<u64>, checksum: Option<u64>) -> u64 {
+    mac.write(&summary_bytes(len, checksum));
+    mac.finish()
+}
+
+#[inline]
+fn u64_bytes(n: u64) -> [u8; 8] {
+    let mut bytes = [0; 8];
+    LittleEndian::write_u64(&mut bytes, n);
+    bytes
+}
+
+/// Returns the hasher for the authentication tag, after it has been given `head`
+///
+/// Its key is a hash of the key of the offsetter,
+/// so that the tag doesn't give anything away about the offsets.
+/// Offsetters without a key can't make tags that anyone else couldn't, so they fail with [`Error::Unsupported`].
+fn auth_hasher<O: Offset>(offsetter: &O, head: &[u8]) -> std::result::Result<SipHasher, Error> {
+    let key = offsetter.key().ok_or(Unsupported)?;
+    let mut kdf = sip128::SipHasher::new_with_keys(LittleEndian::read_u64(&key[..8]), LittleEndian::read_u64(&key[8..]));
+    kdf.write(b"delta-l authentication");
+    let Hash128{h1, h2} = kdf.finish128();
+
+    let mut mac = SipHasher::new_with_keys(h1, h2);
+    mac.write(head);
+    Ok(mac)
+}
+
+/// Returns the key check value of the offsetter's key, if it has one
+fn key_check<O: Offset>(offsetter: &O) -> Option<[u8; 4]> {
+    let key = offsetter.key()?;
+    let mut hasher = SipHasher::new_with_keys(LittleEndian::read_u64(&key[..8]), LittleEndian::read_u64(&key[8..]));
+    hasher.write(b"delta-l key check");
+
+    let mut check = [0; 4];
+    LittleEndian::write_u32(&mut check, hasher.finish() as u32);
+    Some(check)
+}
+
+/// Writes a new random salt and `rounds` and returns the offsetter derived from them
+fn write_salt<W: Write>(passphrase: &str, rounds: u32, dest: &mut W) -> io::Result<PassHashOffsetter> {
+    let salt = random_salt();
+    let mut rounds_bytes = [0; 4];
+    LittleEndian::write_u32(&mut rounds_bytes, rounds);
+
+    dest.write_all(&salt)?;
+    dest.write_all(&rounds_bytes)?;
+    Ok(PassHashOffsetter::derive(passphrase, &salt, rounds))
+}
+
+/// Makes a salt that is very unlikely to have been used before
+///
+/// The keys of `RandomState` are seeded from the operating system's random number generator,
+/// and are combined with the current time.
+fn random_salt() -> [u8; 16] {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+
+    let mut salt = [0; 16];
+    for half in salt.chunks_mut(8) {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(nanos);
+        LittleEndian::write_u64(half, hasher.finish());
+    }
+    salt
+}
+
+/// Reads the magic bytes at the start of the header and returns the letter identifying the header
+fn read_magic<R: Read>(src: &mut R) -> std::result::Result<u8, Error> {
     let mut header = [0; 4];
     src.read_exact(&mut header)?;
 
     if (header[0], header[1], header[3]) == (0xCE, 0x94, b'\n') {
-        match header[2] {
-            b'L' => {
-                let mut cs = [0; 8];
-                src.read_exact(&mut cs)?;
-                let checksum = LittleEndian::read_u64(&cs);
-                decode_with_checksum(offsetter, checksum, src, dest)
-            },
-            b'l' => decode_no_checksum(offsetter, src, dest),
-            _ => Err(InvalidHeader)
-        }
+        Ok(header[2])
     } else {
         Err(InvalidHeader)
     }
 }
 
-fn decode_with_checksum<O: Offset, R: Read, W: Write>(offsetter: O, checksum: u64, src: &mut R, dest: &mut W) -> Result {
-    let mut src = DeltaReader::with_offsetter(src, offsetter);
-    let mut dest = HashingWrite::new(dest);
+/// Everything in the header before the payload
+#[derive(Debug, Clone)]
+struct Parsed {
+    /// The letter in the magic bytes
+    magic: u8,
+    /// Legacy headers are represented as the version 2 header with the same meaning
+    header: Header,
+    /// The delta encoding the header asks for
+    delta: Delta,
+    length: Option<u64>,
+    checksum: Option<u64>,
+    tag: Option<u64>,
+}
 
-    io::copy(&mut src, &mut dest)?;
-    let (dest, hash) = dest.into_inner();
+/// Reads any kind of header
+fn parse<R: Read>(src: &mut R) -> std::result::Result<Parsed, Error> {
+    let magic = read_magic(src)?;
+    let header = match magic {
+        b'L' | b'l' => Header {
+            algorithm: Algorithm::Offsetter,
+            flags: if magic == b'L' { FLAG_CHECKSUM } else { 0 },
+            extensions: Vec::new(),
+        },
+        b'K' | b'k' => {
+            let mut salt = [0; 16];
+            src.read_exact(&mut salt)?;
+            let mut rounds = [0; 4];
+            src.read_exact(&mut rounds)?;
+            let rounds = LittleEndian::read_u32(&rounds);
+            check_rounds(rounds)?;
+
+            Header {
+                algorithm: Algorithm::PassHash{salt, rounds},
+                flags: if magic == b'K' { FLAG_CHECKSUM } else { 0 },
+                extensions: Vec::new(),
+            }
+        }
+        b'V' => Header::read(src)?,
+        _ => return Err(InvalidHeader),
+    };
+
+    let mut parsed = Parsed {
+        magic,
+        delta: delta::from_header(&header)?,
+        header,
+        length: None,
+        checksum: None,
+        tag: None,
+    };
+    if !parsed.header.has_flag(FLAG_TRAILER) {
+        let mut tags = vec![0; parsed.tags_len()];
+        src.read_exact(&mut tags)?;
+        parsed.read_tags(&tags);
+    }
+
+    Ok(parsed)
+}
+
+impl Parsed {
+    /// Returns the data of the first extension field with `tag`
+    fn extension(&self, tag: u8) -> Option<&[u8]> {
+        self.header.extensions.iter()
+            .find(|&&(t, _)| t == tag)
+            .map(|(_, data)| &**data)
+    }
+    /// The length of the plaintext length, checksum and authentication tag together
+    fn tags_len(&self) -> usize {
+        8 * [FLAG_LENGTH, FLAG_CHECKSUM, FLAG_AUTH].iter().filter(|&&flag| self.header.has_flag(flag)).count()
+    }
+    /// The length of the trailer after the payload
+    fn trailer_len(&self) -> usize {
+        if self.header.has_flag(FLAG_TRAILER) {
+            self.tags_len()
+        } else {
+            0
+        }
+    }
+    /// Reads the plaintext length, checksum and authentication tag, `tags` has to be `tags_len` long
+    fn read_tags(&mut self, mut tags: &[u8]) {
+        if self.header.has_flag(FLAG_LENGTH) {
+            self.length = Some(LittleEndian::read_u64(tags));
+            tags = &tags[8..];
+        }
+        if self.header.has_flag(FLAG_CHECKSUM) {
+            self.checksum = Some(LittleEndian::read_u64(tags));
+            tags = &tags[8..];
+        }
+        if self.header.has_flag(FLAG_AUTH) {
+            self.tag = Some(LittleEndian::read_u64(tags));
+        }
+    }
+}
+
+/// Makes sure the header doesn't derive its own offsetter, so the given one is used,
+/// and checks its key
+fn check_given<O: Offset>(offsetter: &O, parsed: &Parsed) -> Result {
+    match parsed.header.algorithm {
+        Algorithm::Offsetter => check_key(offsetter, parsed),
+        _ => Err(PassphraseRequired),
+    }
+}
+
+/// Compares the key check value in the header, if there is one, with the offsetter's
+fn check_key<O: Offset>(offsetter: &O, parsed: &Parsed) -> Result {
+    match parsed.extension(EXT_KEY_CHECK) {
+        Some(stored) if key_check(offsetter).as_ref().map(|c| &c[..]) != Some(stored) => Err(WrongPassphrase),
+        _ => Ok(()),
+    }
+}
+
+/// The offsetters that can be derived from a passphrase
+#[derive(Debug, Clone)]
+pub enum PassphraseOffsetter {
+    /// Used by the unsalted headers and [`Kdf::PassHash`]
+    PassHash(PassHashOffsetter),
+    /// Used by [`Kdf::Keystream`]
+    Keystream(KeystreamOffsetter),
+}
+
+impl PassphraseOffsetter {
+    /// Derives the offsetter the header asks for from the passphrase
+    fn from_header(passphrase: &str, parsed: &Parsed) -> Self {
+        match parsed.header.algorithm {
+            Algorithm::Offsetter => PassphraseOffsetter::PassHash(PassHashOffsetter::new(passphrase)),
+            Algorithm::PassHash{salt, rounds} => PassphraseOffsetter::PassHash(PassHashOffsetter::derive(passphrase, &salt, rounds)),
+            Algorithm::Keystream{salt, rounds} => PassphraseOffsetter::Keystream(KeystreamOffsetter::derive(passphrase, &salt, rounds)),
+        }
+    }
+}
 
-    if checksum != hash {
-        return Err(ChecksumMismatch)
+macro_rules! each_offsetter {
+    ($self:expr, $o:ident => $e:expr) => {
+        match $self {
+            PassphraseOffsetter::PassHash($o) => $e,
+            PassphraseOffsetter::Keystream($o) => $e,
+        }
+    };
+}
+
+impl Offset for PassphraseOffsetter {
+    #[inline]
+    fn next_offset(&mut self) -> u8 {
+        each_offsetter!(self, o => o.next_offset())
+    }
+    #[inline]
+    fn step_back(&mut self) {
+        each_offsetter!(self, o => o.step_back())
+    }
+    #[inline]
+    fn reset(&mut self) {
+        each_offsetter!(self, o => o.reset())
+    }
+    #[inline]
+    fn skip(&mut self, n: u64) {
+        each_offsetter!(self, o => o.skip(n))
+    }
+    #[inline]
+    fn position(&self) -> Option<u64> {
+        each_offsetter!(self, o => o.position())
     }
+    #[inline]
+    fn set_position(&mut self, pos: u64) {
+        each_offsetter!(self, o => o.set_position(pos))
+    }
+    #[inline]
+    fn key(&self) -> Option<[u8; 16]> {
+        each_offsetter!(self, o => o.key())
+    }
+    #[inline]
+    fn sub_offsets(&mut self, buf: &mut [u8]) {
+        each_offsetter!(self, o => o.sub_offsets(buf))
+    }
+}
+
+/// Decodes the `src` into `dest` determining whether to check checksum based on header
+///
+/// Salted headers return [`Error::PassphraseRequired`], use [`decode_with_passphrase`] for those.
+/// The checksum is only checked at the end, so `dest` will have been written to even if it doesn't match,
+/// see [`decode_verified`] for avoiding that.
+/// Armored files, as written by [`ArmorWriter`], are detected and decoded as well.
+///
+/// If the header has an authentication tag, the whole ciphertext is read into memory
+/// so the tag can be checked before anything is written to `dest`.
+/// Use [`decode_verified`] for large files, which reads it twice from `src` instead.
+pub fn decode<O: Offset, R: Read, W: Write>(offsetter: O, src: &mut R, dest: &mut W) -> Result {
+    let mut src = armor::unarmor(src)?;
+    let parsed = parse(&mut src)?;
+    check_given(&offsetter, &parsed)?;
+    decode_parsed(offsetter, &parsed, &mut src, dest)
+}
+
+/// Decodes the `src` into `dest` deriving the offsets from `passphrase` like the header says
+///
+/// Unsalted headers use [`PassHashOffsetter::new`].
+///
+/// Like [`decode`], this reads the whole ciphertext into memory if the header has an authentication tag,
+/// see [`decode_verified_with_passphrase`] for avoiding that.
+pub fn decode_with_passphrase<R: Read, W: Write>(passphrase: &str, src: &mut R, dest: &mut W) -> Result {
+    let mut src = armor::unarmor(src)?;
+    let parsed = parse(&mut src)?;
+    let offsetter = PassphraseOffsetter::from_header(passphrase, &parsed);
+    check_key(&offsetter, &parsed)?;
+    decode_parsed(offsetter, &parsed, &mut src, dest)
+}
+
+/// Like [`decode`], but checks the checksum and authentication tag before writing anything to `dest`
+///
+/// This reads the payload twice, once to check it and once to decode it.
+/// Armored files are read into memory first.
+pub fn decode_verified<O: Offset, R: Read + Seek, W: Write>(offsetter: O, src: &mut R, dest: &mut W) -> Result {
+    if let Some(data) = armor::read_armored(src)? {
+        return decode_verified(offsetter, &mut io::Cursor::new(data), dest)
+    }
+    let parsed = parse(src)?;
+    check_given(&offsetter, &parsed)?;
+    verify_then_decode(offsetter, &parsed, src, dest)
+}
+
+/// Like [`decode_with_passphrase`], but checks the checksum and authentication tag
+/// before writing anything to `dest`
+///
+/// This reads the payload twice, once to check it and once to decode it.
+pub fn decode_verified_with_passphrase<R: Read + Seek, W: Write>(passphrase: &str, src: &mut R, dest: &mut W) -> Result {
+    if let Some(data) = armor::read_armored(src)? {
+        return decode_verified_with_passphrase(passphrase, &mut io::Cursor::new(data), dest)
+    }
+    let parsed = parse(src)?;
+    let offsetter = PassphraseOffsetter::from_header(passphrase, &parsed);
+    check_key(&offsetter, &parsed)?;
+    verify_then_decode(offsetter, &parsed, src, dest)
+}
+
+/// Decodes the payload after the header, and decompresses it if it's compressed
+///
+/// If the header has an authentication tag, the whole payload is read into memory
+/// so it can be checked before anything is written to `dest`.
+fn decode_parsed<O: Offset, R: Read, W: Write>(offsetter: O, parsed: &Parsed, src: &mut R, dest: &mut W) -> Result {
+    #[cfg(feature = "compress")]
+    {
+        if parsed.header.has_flag(FLAG_COMPRESSED) {
+            let mut dest = DecompressWrite::new(dest);
+            decode_delta(offsetter, parsed, src, &mut dest)?;
+            return dest.finish()?.flush().map_err(Into::into)
+        }
+    }
+    decode_delta(offsetter, parsed, src, dest)
+}
+
+fn decode_delta<O: Offset, R: Read, W: Write>(offsetter: O, parsed: &Parsed, src: &mut R, dest: &mut W) -> Result {
+    let trailer_len = parsed.trailer_len();
+
+    if parsed.header.has_flag(FLAG_CHUNKED) {
+        let mut src = ChunksRead::new(src, offsetter, parsed.delta, chunked::chunk_size(parsed)?);
+        io::copy(&mut src, dest)?;
+        dest.flush().map_err(Into::into)
+    } else if parsed.header.has_flag(FLAG_AUTH) {
+        // The tag has to be checked before anything is decoded, and `src` can't be read twice
+        let mut ciphertext = Vec::new();
+        src.read_to_end(&mut ciphertext)?;
+
+        let mut parsed = parsed.clone();
+        if trailer_len > 0 {
+            let payload_len = ciphertext.len().checked_sub(trailer_len)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "the stream ended before the trailer"))?;
+            parsed.read_tags(&ciphertext[payload_len..]);
+            ciphertext.truncate(payload_len);
+        }
+
+        check_length(&parsed, ciphertext.len() as u64)?;
+        verify_tag(&offsetter, &parsed, &mut &*ciphertext)?;
+        decode_payload(offsetter, &parsed, &mut &*ciphertext, dest)
+    } else if trailer_len > 0 {
+        let mut src = TrailerRead::new(src, trailer_len);
+        let (dest, len, hash) = decode_hashed(offsetter, parsed.delta, &mut src, dest)?;
+
+        let mut parsed = parsed.clone();
+        parsed.read_tags(src.trailer()?);
+        check_summary(&parsed, len, hash)?;
+        dest.flush().map_err(Into::into)
+    } else {
+        decode_payload(offsetter, parsed, src, dest)
+    }
+}
+
+fn verify_then_decode<O: Offset, R: Read + Seek, W: Write>(mut offsetter: O, parsed: &Parsed, src: &mut R, dest: &mut W) -> Result {
+    let start = src.stream_position()?;
+
+    if parsed.header.has_flag(FLAG_CHUNKED) {
+        let chunk_size = chunked::chunk_size(parsed)?;
+        io::copy(&mut ChunksRead::new(&mut *src, &mut offsetter, parsed.delta, chunk_size), &mut io::sink())?;
+        src.seek(SeekFrom::Start(start))?;
+        offsetter.reset();
+
+        io::copy(&mut ChunksRead::new(src, offsetter, parsed.delta, chunk_size), dest)?;
+        return dest.flush().map_err(Into::into)
+    }
+    let mut payload_len = u64::MAX;
+
+    let mut parsed = parsed.clone();
+    let trailer_len = parsed.trailer_len();
+    if trailer_len > 0 || parsed.header.has_flag(FLAG_LENGTH) {
+        let end = src.seek(SeekFrom::End(0))?;
+        payload_len = (end - start).checked_sub(trailer_len as u64)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "the stream ended before the trailer"))?;
+
+        if trailer_len > 0 {
+            let mut trailer = vec![0; trailer_len];
+            src.seek(SeekFrom::Start(start + payload_len))?;
+            src.read_exact(&mut trailer)?;
+            parsed.read_tags(&trailer);
+        }
+        src.seek(SeekFrom::Start(start))?;
+        // The ciphertext is as long as the plaintext, so a truncated file is found before decoding
+        check_length(&parsed, payload_len)?;
+    }
+
+    if parsed.tag.is_some() {
+        verify_tag(&offsetter, &parsed, &mut (&mut *src).take(payload_len))?;
+        src.seek(SeekFrom::Start(start))?;
+    }
+    if parsed.checksum.is_some() {
+        decode_payload(&mut offsetter, &parsed, &mut (&mut *src).take(payload_len), &mut io::sink())?;
+        src.seek(SeekFrom::Start(start))?;
+        offsetter.reset();
+    }
+
+    #[cfg(feature = "compress")]
+    {
+        if parsed.header.has_flag(FLAG_COMPRESSED) {
+            let mut dest = DecompressWrite::new(dest);
+            decode_no_checksum(offsetter, parsed.delta, &mut src.take(payload_len), &mut dest)?;
+            return dest.finish()?.flush().map_err(Into::into)
+        }
+    }
+    decode_no_checksum(offsetter, parsed.delta, &mut src.take(payload_len), dest)
+}
+
+/// Reads the rest of `ciphertext` and checks it against the authentication tag
+fn verify_tag<O: Offset, R: Read>(offsetter: &O, parsed: &Parsed, ciphertext: &mut R) -> Result {
+    let mut mac = HashingWrite::with_hasher(io::sink(), auth_hasher(offsetter, &parsed.header.to_bytes())?);
+    io::copy(ciphertext, &mut mac)?;
+    let (_, mac) = mac.into_parts();
+
+    if Some(finish_mac(mac, parsed.length, parsed.checksum)) != parsed.tag {
+        return Err(AuthenticationFailed)
+    }
+    Ok(())
+}
+
+/// Compares the length of the payload with the stored length, if there is one
+fn check_length(parsed: &Parsed, actual: u64) -> Result {
+    match parsed.length {
+        Some(expected) if actual < expected => Err(Truncated{expected, actual}),
+        Some(expected) if actual > expected => Err(TrailingData),
+        _ => Ok(()),
+    }
+}
+
+/// Compares the length and checksum of the plaintext with the stored ones
+fn check_summary(parsed: &Parsed, len: u64, hash: u64) -> Result {
+    check_length(parsed, len)?;
+    match parsed.checksum {
+        Some(checksum) if checksum != hash => Err(ChecksumMismatch),
+        _ => Ok(()),
+    }
+}
+
+#[inline]
+fn decode_payload<O: Offset, R: Read, W: Write>(offsetter: O, parsed: &Parsed, src: &mut R, dest: &mut W) -> Result {
+    if parsed.length.is_none() && parsed.checksum.is_none() {
+        return decode_no_checksum(offsetter, parsed.delta, src, dest)
+    }
+    let (dest, len, hash) = decode_hashed(offsetter, parsed.delta, src, dest)?;
+    check_summary(parsed, len, hash)?;
 
     dest.flush().map_err(Into::into)
 }
 
-fn decode_no_checksum<O: Offset, R: Read, W: Write>(offsetter: O, src: &mut R, dest: &mut W) -> Result {
-    let mut src = DeltaReader::with_offsetter(src, offsetter);
+/// Decodes `src` into `dest` and returns the length and checksum of what was written
+fn decode_hashed<O: Offset, R: Read, W: Write>(offsetter: O, delta: Delta, src: &mut R, dest: W) -> io::Result<(W, u64, u64)> {
+    let mut src = DeltaReader::with_delta(src, offsetter, delta);
+    let mut dest = HashingWrite::new(dest);
+
+    io::copy(&mut src, &mut dest)?;
+    let len = dest.count();
+    let (dest, hash) = dest.into_inner();
+    Ok((dest, len, hash))
+}
+
+fn decode_no_checksum<O: Offset, R: Read, W: Write>(offsetter: O, delta: Delta, src: &mut R, dest: &mut W) -> Result {
+    let mut src = DeltaReader::with_delta(src, offsetter, delta);
     io::copy(&mut src, dest)?;
     dest.flush().map_err(Into::into)
 }
@@ -0,0 +1,263 @@
+//! ASCII armor, which turns encoded files into text that can be pasted into emails and configuration files
+//!
+//! The armor is a `-----BEGIN DELTA-L-----` line, the data in base64 with lines of 64 characters,
+//! and an `-----END DELTA-L-----` line.
+
+use std::io::{self, Read, Write, Seek, SeekFrom, BufRead, BufReader, Cursor};
+
+/// The line the armor starts with
+pub const BEGIN: &str = "-----BEGIN DELTA-L-----";
+/// The line the armor ends with
+pub const END: &str = "-----END DELTA-L-----";
+
+const LINE_LEN: usize = 64;
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// A `Write`r that writes what's written to it in armor
+///
+/// The armor is only complete once [`finish`](ArmorWriter::finish) has been called.
+#[derive(Debug)]
+pub struct ArmorWriter<W: Write> {
+    inner: W,
+    /// Bytes that don't fill a group of 3 yet
+    rest: Vec<u8>,
+    line: Vec<u8>,
+    started: bool,
+}
+
+impl<W: Write> ArmorWriter<W> {
+    /// Makes an `ArmorWriter` that writes to `writer`
+    pub fn new(writer: W) -> Self {
+        ArmorWriter {
+            inner: writer,
+            rest: Vec::with_capacity(3),
+            line: Vec::with_capacity(LINE_LEN + 1),
+            started: false,
+        }
+    }
+    fn start(&mut self) -> io::Result<()> {
+        if !self.started {
+            writeln!(self.inner, "{}", BEGIN)?;
+            self.started = true;
+        }
+        Ok(())
+    }
+    /// Adds the base64 of a group of up to 3 bytes to the line, writing the line once it's full
+    fn push_group(&mut self, group: &[u8]) -> io::Result<()> {
+        let mut bytes = [0; 3];
+        bytes[..group.len()].copy_from_slice(group);
+        let n = u32::from(bytes[0]) << 16 | u32::from(bytes[1]) << 8 | u32::from(bytes[2]);
+
+        for i in 0..4 {
+            if i <= group.len() {
+                self.line.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize]);
+            } else {
+                self.line.push(b'=');
+            }
+        }
+        if self.line.len() == LINE_LEN {
+            self.line.push(b'\n');
+            self.inner.write_all(&self.line)?;
+            self.line.clear();
+        }
+        Ok(())
+    }
+    /// Writes the rest of the data and the end line and returns the inner `Write`r
+    pub fn finish(mut self) -> io::Result<W> {
+        self.start()?;
+        if !self.rest.is_empty() {
+            let rest = std::mem::take(&mut self.rest);
+            self.push_group(&rest)?;
+        }
+        if !self.line.is_empty() {
+            self.line.push(b'\n');
+            self.inner.write_all(&self.line)?;
+        }
+        writeln!(self.inner, "{}", END)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for ArmorWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.start()?;
+
+        let mut bytes = buf;
+        if !self.rest.is_empty() {
+            let n = bytes.len().min(3 - self.rest.len());
+            self.rest.extend_from_slice(&bytes[..n]);
+            bytes = &bytes[n..];
+            if self.rest.len() < 3 {
+                return Ok(buf.len())
+            }
+            let rest = std::mem::take(&mut self.rest);
+            self.push_group(&rest)?;
+        }
+
+        let mut groups = bytes.chunks_exact(3);
+        for group in &mut groups {
+            self.push_group(group)?;
+        }
+        self.rest.extend_from_slice(groups.remainder());
+
+        Ok(buf.len())
+    }
+    /// Flushes the inner `Write`r, the bytes of a line that isn't full yet stay in the `ArmorWriter`
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A `Read`er that reads the data out of armor
+///
+/// Anything after the end line is read into the buffer of the `ArmorReader` and is lost.
+#[derive(Debug)]
+pub struct ArmorReader<R: Read> {
+    inner: BufReader<R>,
+    line: String,
+    /// Base64 characters that don't fill a group of 4 yet
+    quad: Vec<u8>,
+    out: Vec<u8>,
+    pos: usize,
+    started: bool,
+    /// Padding has been read, so no more data can follow
+    padded: bool,
+    done: bool,
+}
+
+impl<R: Read> ArmorReader<R> {
+    /// Makes an `ArmorReader` that reads from `reader`, which should start with the begin line
+    pub fn new(reader: R) -> Self {
+        ArmorReader {
+            inner: BufReader::new(reader),
+            line: String::new(),
+            quad: Vec::with_capacity(4),
+            out: Vec::new(),
+            pos: 0,
+            started: false,
+            padded: false,
+            done: false,
+        }
+    }
+    /// Decodes the next line into `out`
+    fn read_line(&mut self) -> io::Result<()> {
+        self.line.clear();
+        if self.inner.read_line(&mut self.line)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the armor has no end line"))
+        }
+        let line = self.line.trim();
+
+        if !self.started {
+            if line != BEGIN {
+                return Err(invalid("the armor has no begin line"))
+            }
+            self.started = true;
+            return Ok(())
+        }
+        if line == END {
+            if !self.quad.is_empty() {
+                return Err(invalid("the armor ends in the middle of a group"))
+            }
+            self.done = true;
+            return Ok(())
+        }
+
+        for c in line.bytes().filter(|c| !c.is_ascii_whitespace()) {
+            if self.padded {
+                return Err(invalid("the armor has data after the padding"))
+            }
+            self.quad.push(c);
+            if self.quad.len() == 4 {
+                let quad = std::mem::take(&mut self.quad);
+                self.padded = decode_quad(&quad, &mut self.out)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Decodes a group of 4 base64 characters into `out`, returning whether it was padded
+fn decode_quad(quad: &[u8], out: &mut Vec<u8>) -> io::Result<bool> {
+    let padding = quad.iter().rev().take_while(|&&c| c == b'=').count();
+    if padding > 2 {
+        return Err(invalid("the armor has too much padding"))
+    }
+
+    let mut n = 0;
+    for &c in &quad[..4 - padding] {
+        let value = ALPHABET.iter().position(|&a| a == c).ok_or_else(|| invalid("the armor has a character that isn't base64"))?;
+        n = n << 6 | value as u32;
+    }
+    n <<= 6 * padding;
+
+    out.extend_from_slice(&[(n >> 16) as u8, (n >> 8) as u8, n as u8][..3 - padding]);
+    Ok(padding > 0)
+}
+
+impl<R: Read> Read for ArmorReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.out.len() && !self.done && !buf.is_empty() {
+            self.out.clear();
+            self.pos = 0;
+            self.read_line()?;
+        }
+
+        let n = buf.len().min(self.out.len() - self.pos);
+        buf[..n].copy_from_slice(&self.out[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// A `Read`er that removes the armor from what it reads, if there is any
+pub(super) enum Unarmored<R: Read> {
+    Binary(io::Chain<Cursor<Vec<u8>>, R>),
+    Armored(ArmorReader<io::Chain<Cursor<Vec<u8>>, R>>),
+}
+
+/// Looks at the first byte of `src` to find out whether it's armored
+pub(super) fn unarmor<R: Read>(mut src: R) -> io::Result<Unarmored<R>> {
+    let mut first = [0];
+    let n = loop {
+        match src.read(&mut first) {
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            res => break res?,
+        }
+    };
+    let src = Cursor::new(first[..n].to_vec()).chain(src);
+
+    Ok(if first[..n] == *b"-" {
+        Unarmored::Armored(ArmorReader::new(src))
+    } else {
+        Unarmored::Binary(src)
+    })
+}
+
+impl<R: Read> Read for Unarmored<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Unarmored::Binary(ref mut src) => src.read(buf),
+            Unarmored::Armored(ref mut src) => src.read(buf),
+        }
+    }
+}
+
+/// Reads the data out of `src` if it's armored, otherwise leaves `src` where it was
+pub(super) fn read_armored<R: Read + Seek>(src: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let start = src.stream_position()?;
+    let mut first = [0];
+    let n = src.read(&mut first)?;
+    src.seek(SeekFrom::Start(start))?;
+
+    if first[..n] != *b"-" {
+        return Ok(None)
+    }
+    let mut data = Vec::new();
+    ArmorReader::new(src).read_to_end(&mut data)?;
+    Ok(Some(data))
+}
@@ -0,0 +1,541 @@
+//! The chunked payload of the version 2 header (`FLAG_CHUNKED`)
+//!
+//! The plaintext is split into chunks of the size stored in the header, the last one may be shorter.
+//! Each chunk is delta encoded on its own, starting as at the start of a stream,
+//! while the offsets continue through the whole file. The payload is made of records,
+//! one for each chunk, holding the length (`u32`), the plaintext checksum (`u64`) and the ciphertext,
+//! and is ended by a record with a length of 0 and no ciphertext.
+//!
+//! After it comes the index: the offset of each record from the start of the payload (`u64`)
+//! and the checksum of its chunk (`u64`), followed by the number of chunks (`u64`).
+
+use byteorder::{LittleEndian, ByteOrder};
+
+use std::io::{self, Read, Write, Seek, SeekFrom};
+use std::hash::Hasher;
+use std::{panic, thread};
+
+use siphasher::sip::SipHasher;
+
+use crate::{Offset, Delta, DeltaReader};
+
+use super::v2::{Header, Algorithm, FLAG_AUTH, FLAG_LENGTH, FLAG_CHUNKED, FLAG_COMPRESSED, FLAG_DELTA, EXT_CHUNK_SIZE};
+use super::armor;
+use super::{Error, Kdf, Options, Parsed, PassphraseOffsetter, parse, check_given, check_key, derive_offsetter, verify_then_decode};
+use super::Error::{InvalidHeader, ChecksumMismatch, Unsupported};
+
+/// Length of the length and checksum before each chunk
+const RECORD_HEAD_LEN: usize = 12;
+
+/// Encodes the `src` into `dest` using the version 2 header with a chunked payload
+///
+/// Every chunk of `chunk_size` bytes has its own checksum, so a damaged chunk doesn't affect the others,
+/// and each chunk can be decoded on its own with a [`ChunkedReader`]. The checksum option is ignored,
+/// as every chunk is checksummed. `dest` doesn't need to be seekable.
+///
+/// Chunked files can't be authenticated, store the length or be compressed,
+/// so asking for any of those fails with [`Error::Unsupported`] before anything is written.
+///
+/// # Panics
+/// If `chunk_size` is 0
+pub fn encode_chunked<O: Offset, R: Read, W: Write>(offsetter: O, chunk_size: u32, options: &Options, src: &mut R, dest: &mut W) -> super::Result {
+    let header = options.header(&offsetter, Algorithm::Offsetter, false);
+    encode_chunks(offsetter, options.delta, chunk_size, header, src, dest)
+}
+
+/// Like [`encode_chunked`] with offsets derived from the passphrase with a random salt
+///
+/// # Panics
+/// If `chunk_size` is 0
+pub fn encode_chunked_with_passphrase<R: Read, W: Write>(passphrase: &str, kdf: Kdf, chunk_size: u32, options: &Options, src: &mut R, dest: &mut W) -> super::Result {
+    let (offsetter, algorithm) = derive_offsetter(passphrase, kdf)?;
+    let header = options.header(&offsetter, algorithm, false);
+    encode_chunks(offsetter, options.delta, chunk_size, header, src, dest)
+}
+
+fn encode_chunks<O: Offset, R: Read, W: Write>(mut offsetter: O, delta: Delta, chunk_size: u32, header: Header, src: &mut R, dest: &mut W) -> super::Result {
+    let mut dest = ChunkWriter::new(dest, chunk_size, header)?;
+
+    let mut chunk = vec![0; chunk_size as usize];
+    loop {
+        let len = read_chunk(src, &mut chunk)?;
+        if len == 0 {
+            break
+        }
+        let chunk = &mut chunk[..len];
+
+        let checksum = checksum(chunk);
+        let mut delta = delta;
+        delta.encode(chunk, &mut offsetter);
+        dest.write_chunk(chunk, checksum)?;
+    }
+
+    dest.finish()
+}
+
+/// Like [`encode_chunked`], but encodes `threads` chunks at a time on their own threads
+///
+/// This needs up to `threads` chunks in memory at once.
+///
+/// # Panics
+/// If `chunk_size` is 0
+pub fn encode_parallel<O: Offset + Clone + Sync, R: Read, W: Write>(offsetter: O, chunk_size: u32, threads: usize, options: &Options, src: &mut R, dest: &mut W) -> super::Result {
+    let header = options.header(&offsetter, Algorithm::Offsetter, false);
+    encode_chunks_parallel(offsetter, options.delta, chunk_size, threads, header, src, dest)
+}
+
+/// Like [`encode_chunked_with_passphrase`], but encodes `threads` chunks at a time on their own threads
+///
+/// # Panics
+/// If `chunk_size` is 0
+pub fn encode_parallel_with_passphrase<R: Read, W: Write>(passphrase: &str, kdf: Kdf, chunk_size: u32, threads: usize, options: &Options, src: &mut R, dest: &mut W) -> super::Result {
+    let (offsetter, algorithm) = derive_offsetter(passphrase, kdf)?;
+    let header = options.header(&offsetter, algorithm, false);
+    encode_chunks_parallel(offsetter, options.delta, chunk_size, threads, header, src, dest)
+}
+
+fn encode_chunks_parallel<O: Offset + Clone + Sync, R: Read, W: Write>(offsetter: O, delta: Delta, chunk_size: u32, threads: usize, header: Header, src: &mut R, dest: &mut W) -> super::Result {
+    let mut dest = ChunkWriter::new(dest, chunk_size, header)?;
+
+    let threads = threads.max(1);
+    // The buffers are only made once the chunks before them are full, so short files don't need `threads` of them
+    let mut batch: Vec<Vec<u8>> = Vec::new();
+    let mut first_index = 0;
+    loop {
+        // Only the last chunk can be short, so the batch ends there
+        let mut lens = Vec::with_capacity(batch.len());
+        while lens.len() < threads {
+            if batch.len() == lens.len() {
+                batch.push(vec![0; chunk_size as usize]);
+            }
+            let chunk = &mut batch[lens.len()];
+            let len = read_chunk(src, chunk)?;
+            if len > 0 {
+                lens.push(len);
+            }
+            if len < chunk.len() {
+                break
+            }
+        }
+        if lens.is_empty() {
+            break
+        }
+
+        let checksums = in_parallel(&mut batch[..lens.len()], |i, chunk| {
+            let chunk = &mut chunk[..lens[i]];
+            let mut offsetter = offsetter.clone();
+            offsetter.set_position((first_index + i) as u64 * u64::from(chunk_size));
+
+            let checksum = checksum(chunk);
+            let mut delta = delta;
+            delta.encode(chunk, &mut offsetter);
+            checksum
+        });
+        for ((chunk, &len), checksum) in batch.iter().zip(&lens).zip(checksums) {
+            dest.write_chunk(&chunk[..len], checksum)?;
+        }
+
+        if lens.len() < threads || lens[lens.len() - 1] < chunk_size as usize {
+            break
+        }
+        first_index += lens.len();
+    }
+
+    dest.finish()
+}
+
+/// Runs `f` on every chunk on its own thread, with the index of the chunk, and returns the results in order
+fn in_parallel<T: Send, F: Fn(usize, &mut Vec<u8>) -> T + Sync>(chunks: &mut [Vec<u8>], f: F) -> Vec<T> {
+    if chunks.len() == 1 {
+        return vec![f(0, &mut chunks[0])]
+    }
+
+    let f = &f;
+    thread::scope(|scope| {
+        let handles: Vec<_> = chunks.iter_mut()
+            .enumerate()
+            .map(|(i, chunk)| scope.spawn(move || f(i, chunk)))
+            .collect();
+        handles.into_iter()
+            .map(|handle| handle.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+            .collect()
+    })
+}
+
+/// Writes the header, the records and the index
+struct ChunkWriter<W: Write> {
+    dest: W,
+    index: Vec<u8>,
+    offset: u64,
+}
+
+impl<W: Write> ChunkWriter<W> {
+    fn new(mut dest: W, chunk_size: u32, mut header: Header) -> Result<Self, Error> {
+        assert!(chunk_size > 0, "the chunk size can't be 0");
+        if header.has_flag(FLAG_AUTH | FLAG_LENGTH | FLAG_COMPRESSED) {
+            return Err(Unsupported)
+        }
+
+        // Every chunk has its own checksum instead
+        header.flags = FLAG_CHUNKED | header.flags & FLAG_DELTA;
+        let mut size = [0; 4];
+        LittleEndian::write_u32(&mut size, chunk_size);
+        header.extensions.insert(0, (EXT_CHUNK_SIZE, size.to_vec()));
+        dest.write_all(&header.to_bytes())?;
+
+        Ok(ChunkWriter {
+            dest,
+            index: Vec::new(),
+            offset: 0,
+        })
+    }
+    fn write_chunk(&mut self, ciphertext: &[u8], checksum: u64) -> io::Result<()> {
+        self.dest.write_all(&record_head(ciphertext.len() as u32, checksum))?;
+        self.dest.write_all(ciphertext)?;
+
+        let mut entry = [0; 16];
+        LittleEndian::write_u64(&mut entry[..8], self.offset);
+        LittleEndian::write_u64(&mut entry[8..], checksum);
+        self.index.extend_from_slice(&entry);
+        self.offset += (RECORD_HEAD_LEN + ciphertext.len()) as u64;
+        Ok(())
+    }
+    fn finish(mut self) -> super::Result {
+        self.dest.write_all(&record_head(0, 0))?;
+
+        let mut count = [0; 8];
+        LittleEndian::write_u64(&mut count, (self.index.len() / 16) as u64);
+        self.dest.write_all(&self.index)?;
+        self.dest.write_all(&count)?;
+
+        self.dest.flush().map_err(Into::into)
+    }
+}
+
+#[inline]
+fn checksum(plaintext: &[u8]) -> u64 {
+    let mut hasher = SipHasher::new();
+    hasher.write(plaintext);
+    hasher.finish()
+}
+
+/// Fills `chunk` as far as `src` goes and returns how much was read
+fn read_chunk<R: Read>(src: &mut R, chunk: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < chunk.len() {
+        match src.read(&mut chunk[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(len)
+}
+
+#[inline]
+fn record_head(len: u32, checksum: u64) -> [u8; RECORD_HEAD_LEN] {
+    let mut head = [0; RECORD_HEAD_LEN];
+    LittleEndian::write_u32(&mut head[..4], len);
+    LittleEndian::write_u64(&mut head[4..], checksum);
+    head
+}
+
+/// Returns the chunk size stored in the header
+pub(super) fn chunk_size(parsed: &Parsed) -> Result<u32, Error> {
+    // The chunks are read on their own, so they can't be compressed as one stream
+    if parsed.header.has_flag(FLAG_COMPRESSED) {
+        return Err(Unsupported)
+    }
+    match parsed.extension(EXT_CHUNK_SIZE) {
+        Some(size) if size.len() == 4 && LittleEndian::read_u32(size) > 0 => Ok(LittleEndian::read_u32(size)),
+        _ => Err(InvalidHeader),
+    }
+}
+
+/// Like [`decode_verified`](super::decode_verified), but chunked files are decoded `threads` chunks at a time
+/// on their own threads
+///
+/// Every chunk is checked before anything is written to `dest`.
+/// Files that aren't chunked are decoded like with `decode_verified`.
+pub fn decode_parallel<O: Offset + Clone + Sync, R: Read + Seek, W: Write>(offsetter: O, threads: usize, src: &mut R, dest: &mut W) -> super::Result {
+    if let Some(data) = armor::read_armored(src)? {
+        return decode_parallel(offsetter, threads, &mut io::Cursor::new(data), dest)
+    }
+    let parsed = parse(src)?;
+    check_given(&offsetter, &parsed)?;
+    decode_parsed_parallel(offsetter, threads, &parsed, src, dest)
+}
+
+/// Like [`decode_verified_with_passphrase`](super::decode_verified_with_passphrase),
+/// but chunked files are decoded `threads` chunks at a time on their own threads
+///
+/// Every chunk is checked before anything is written to `dest`.
+pub fn decode_parallel_with_passphrase<R: Read + Seek, W: Write>(passphrase: &str, threads: usize, src: &mut R, dest: &mut W) -> super::Result {
+    if let Some(data) = armor::read_armored(src)? {
+        return decode_parallel_with_passphrase(passphrase, threads, &mut io::Cursor::new(data), dest)
+    }
+    let parsed = parse(src)?;
+    let offsetter = PassphraseOffsetter::from_header(passphrase, &parsed);
+    check_key(&offsetter, &parsed)?;
+    decode_parsed_parallel(offsetter, threads, &parsed, src, dest)
+}
+
+fn decode_parsed_parallel<O: Offset + Clone + Sync, R: Read + Seek, W: Write>(offsetter: O, threads: usize, parsed: &Parsed, src: &mut R, dest: &mut W) -> super::Result {
+    if !parsed.header.has_flag(FLAG_CHUNKED) {
+        return verify_then_decode(offsetter, parsed, src, dest)
+    }
+    let chunk_size = chunk_size(parsed)?;
+
+    let start = src.stream_position()?;
+    decode_chunks_parallel(&offsetter, parsed.delta, chunk_size, threads, src, &mut io::sink())?;
+    src.seek(SeekFrom::Start(start))?;
+    decode_chunks_parallel(&offsetter, parsed.delta, chunk_size, threads, src, dest)?;
+
+    dest.flush().map_err(Into::into)
+}
+
+/// Decodes the records `threads` at a time and writes them to `dest` if they all match their checksums
+fn decode_chunks_parallel<O: Offset + Clone + Sync, R: Read, W: Write>(offsetter: &O, delta: Delta, chunk_size: u32, threads: usize, src: &mut R, dest: &mut W) -> super::Result {
+    let mut batch = vec![Vec::new(); threads.max(1)];
+    let mut checksums = Vec::with_capacity(batch.len());
+    let mut first_index = 0;
+    let mut done = false;
+    // Only the last chunk can be short, as the offsets of the ones after it would be off
+    let mut short = false;
+
+    while !done {
+        checksums.clear();
+        for chunk in &mut batch {
+            let mut head = [0; RECORD_HEAD_LEN];
+            src.read_exact(&mut head)?;
+            let len = LittleEndian::read_u32(&head[..4]);
+            if len == 0 {
+                done = true;
+                break
+            }
+            if len > chunk_size || short {
+                return Err(InvalidHeader)
+            }
+            short = len < chunk_size;
+
+            // The lengths aren't trusted, so the buffer only grows as far as there's data for it
+            chunk.clear();
+            if src.by_ref().take(len.into()).read_to_end(chunk)? < len as usize {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the stream ended in the middle of a chunk").into())
+            }
+            checksums.push(LittleEndian::read_u64(&head[4..]));
+        }
+
+        let results = in_parallel(&mut batch[..checksums.len()], |i, chunk| {
+            let mut offsetter = offsetter.clone();
+            offsetter.set_position((first_index + i) as u64 * u64::from(chunk_size));
+            let mut delta = delta;
+            delta.decode(chunk, &mut offsetter);
+            checksum(chunk)
+        });
+        if results != checksums {
+            return Err(ChecksumMismatch)
+        }
+
+        for chunk in &batch[..checksums.len()] {
+            dest.write_all(chunk)?;
+        }
+        first_index += checksums.len();
+    }
+
+    // The index is only needed for random access
+    io::copy(src, &mut io::sink())?;
+    Ok(())
+}
+
+/// A `Read`er that decodes the records of a chunked payload one after the other
+///
+/// A chunk that doesn't match its checksum is returned as an `io::Error` wrapping [`Error::ChecksumMismatch`]
+/// once the whole chunk has been read.
+pub(super) struct ChunksRead<R: Read, O: Offset> {
+    /// Decodes the ciphertext of the current chunk
+    inner: DeltaReader<io::Take<R>, O>,
+    chunk_size: u32,
+    /// What's left of the current chunk
+    left: u32,
+    checksum: u64,
+    hasher: SipHasher,
+    /// Whether a chunk has been shorter than the chunk size, which only the last one can be
+    short: bool,
+    done: bool,
+}
+
+impl<R: Read, O: Offset> ChunksRead<R, O> {
+    #[inline]
+    pub fn new(inner: R, offsetter: O, delta: Delta, chunk_size: u32) -> Self {
+        ChunksRead {
+            inner: DeltaReader::with_delta(inner.take(0), offsetter, delta),
+            chunk_size,
+            left: 0,
+            checksum: 0,
+            hasher: SipHasher::new(),
+            short: false,
+            done: false,
+        }
+    }
+}
+
+impl<R: Read, O: Offset> Read for ChunksRead<R, O> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.done || buf.is_empty() {
+            return Ok(0)
+        }
+
+        if self.left == 0 {
+            let src = self.inner.get_mut().get_mut();
+            let mut head = [0; RECORD_HEAD_LEN];
+            src.read_exact(&mut head)?;
+            let len = LittleEndian::read_u32(&head[..4]);
+            if len == 0 {
+                // The index is only needed for random access
+                io::copy(src, &mut io::sink())?;
+                self.done = true;
+                return Ok(0)
+            }
+            if len > self.chunk_size || self.short {
+                return Err(InvalidHeader.into())
+            }
+            self.short = len < self.chunk_size;
+
+            self.left = len;
+            self.checksum = LittleEndian::read_u64(&head[4..]);
+            self.hasher = SipHasher::new();
+            // The chunk is read as a stream of its own, so its last bytes are decoded when the end of it is reached
+            self.inner.get_mut().set_limit(len.into());
+            self.inner.restart();
+        }
+
+        let n = self.inner.read(buf)?;
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the stream ended in the middle of a chunk"))
+        }
+        self.hasher.write(&buf[..n]);
+        self.left -= n as u32;
+
+        if self.left == 0 && self.hasher.finish() != self.checksum {
+            return Err(ChecksumMismatch.into())
+        }
+        Ok(n)
+    }
+}
+
+/// Decodes single chunks of a chunked file
+///
+/// The header and the index at the end are read when it's made,
+/// after which any chunk can be decoded without decoding the ones before it.
+#[derive(Debug)]
+pub struct ChunkedReader<R: Read + Seek, O: Offset = PassphraseOffsetter> {
+    inner: R,
+    offsetter: O,
+    delta: Delta,
+    chunk_size: u32,
+    payload_start: u64,
+    /// The offset of each record from the start of the payload and its checksum
+    index: Vec<(u64, u64)>,
+}
+
+impl<R: Read + Seek, O: Offset> ChunkedReader<R, O> {
+    /// Reads the header and index of a chunked file using the given offsetter
+    ///
+    /// Files that aren't chunked return [`Error::InvalidHeader`].
+    pub fn new(mut reader: R, offsetter: O) -> Result<Self, Error> {
+        let parsed = parse(&mut reader)?;
+        check_given(&offsetter, &parsed)?;
+        Self::with_parsed(reader, offsetter, &parsed)
+    }
+    fn with_parsed(mut reader: R, offsetter: O, parsed: &Parsed) -> Result<Self, Error> {
+        if !parsed.header.has_flag(FLAG_CHUNKED) {
+            return Err(InvalidHeader)
+        }
+        let chunk_size = chunk_size(parsed)?;
+        let payload_start = reader.stream_position()?;
+
+        let end = reader.seek(SeekFrom::End(0))?;
+        let mut count = [0; 8];
+        reader.seek(SeekFrom::Start(end.checked_sub(8).ok_or(InvalidHeader)?))?;
+        reader.read_exact(&mut count)?;
+        let count = LittleEndian::read_u64(&count);
+
+        let index_len = count.checked_mul(16).filter(|&len| len + 8 <= end - payload_start).ok_or(InvalidHeader)?;
+        let mut index = vec![0; index_len as usize];
+        reader.seek(SeekFrom::Start(end - 8 - index_len))?;
+        reader.read_exact(&mut index)?;
+
+        Ok(ChunkedReader {
+            inner: reader,
+            offsetter,
+            delta: parsed.delta,
+            chunk_size,
+            payload_start,
+            index: index.chunks(16)
+                .map(|entry| (LittleEndian::read_u64(&entry[..8]), LittleEndian::read_u64(&entry[8..])))
+                .collect(),
+        })
+    }
+    /// The size of every chunk but the last
+    #[inline]
+    pub fn chunk_size(&self) -> u32 {
+        self.chunk_size
+    }
+    /// The number of chunks
+    #[inline]
+    pub fn chunk_count(&self) -> usize {
+        self.index.len()
+    }
+    /// Decodes the chunk at `index` into `buf`, replacing what was in it
+    ///
+    /// Returns [`Error::ChecksumMismatch`] if it doesn't match its checksum.
+    ///
+    /// # Panics
+    /// If `index` isn't below [`chunk_count`](ChunkedReader::chunk_count)
+    pub fn read_chunk(&mut self, index: usize, buf: &mut Vec<u8>) -> super::Result {
+        let (offset, checksum) = self.index[index];
+        // Every chunk but the last is whole, so the positions of the records and offsets follow from the index
+        if offset != index as u64 * (RECORD_HEAD_LEN as u64 + u64::from(self.chunk_size)) {
+            return Err(InvalidHeader)
+        }
+
+        let mut head = [0; RECORD_HEAD_LEN];
+        self.inner.seek(SeekFrom::Start(self.payload_start + offset))?;
+        self.inner.read_exact(&mut head)?;
+        let len = LittleEndian::read_u32(&head[..4]);
+        let last = index + 1 == self.index.len();
+        if len == 0 || len > self.chunk_size || (!last && len != self.chunk_size) {
+            return Err(InvalidHeader)
+        }
+
+        buf.clear();
+        buf.resize(len as usize, 0);
+        self.inner.read_exact(buf)?;
+
+        self.offsetter.set_position(index as u64 * u64::from(self.chunk_size));
+        let mut delta = self.delta;
+        delta.decode(buf, &mut self.offsetter);
+
+        if self::checksum(buf) != checksum {
+            return Err(ChecksumMismatch)
+        }
+        Ok(())
+    }
+    /// Returns the inner `Read`er
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read + Seek> ChunkedReader<R> {
+    /// Reads the header and index of a chunked file deriving the offsets from `passphrase` like the header says
+    pub fn with_passphrase(mut reader: R, passphrase: &str) -> Result<Self, Error> {
+        let parsed = parse(&mut reader)?;
+        let offsetter = PassphraseOffsetter::from_header(passphrase, &parsed);
+        check_key(&offsetter, &parsed)?;
+        Self::with_parsed(reader, offsetter, &parsed)
+    }
+}
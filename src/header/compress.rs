@@ -0,0 +1,351 @@
+//! A small LZ77 compressor in the style of LZ4, used on the plaintext before the delta encoding
+//!
+//! The compressed stream is made of blocks of at most [`BLOCK_SIZE`] bytes of input,
+//! each compressed on its own. A block starts with its length (`u32`),
+//! whose top bit is set if the input is stored as it is because compressing didn't make it smaller.
+//! A length of 0 ends the stream.
+//!
+//! A compressed block is a list of sequences, each made of:
+//!
+//! - a token byte, with the number of literals in the high 4 bits and the match length minus 4 in the low 4 bits,
+//!   where 15 means that bytes follow that are added on until one isn't 255
+//! - the literals
+//! - the distance back to the start of the match (`u16`), and any bytes of the match length,
+//!   except in the last sequence, which ends the block after its literals
+
+use byteorder::{LittleEndian, ByteOrder};
+
+use std::io::{self, Read, Write};
+
+/// The largest number of bytes of input in a block
+pub(super) const BLOCK_SIZE: usize = 1 << 16;
+
+const STORED: u32 = 1 << 31;
+const MIN_MATCH: usize = 4;
+const HASH_BITS: u32 = 12;
+
+fn corrupt() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "the compressed data is corrupt")
+}
+
+fn hash(bytes: &[u8]) -> usize {
+    (LittleEndian::read_u32(bytes).wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+}
+
+/// Appends `block`, which can't be longer than [`BLOCK_SIZE`], compressed and with its length to `out`
+pub(super) fn compress_block(block: &[u8], out: &mut Vec<u8>) {
+    debug_assert!(block.len() <= BLOCK_SIZE);
+    let start = out.len();
+    out.extend_from_slice(&[0; 4]);
+
+    // The last position + 1 of each hash of 4 bytes, 0 if there hasn't been one
+    let mut table = vec![0u32; 1 << HASH_BITS];
+    let mut literals = 0;
+    let mut pos = 0;
+    while pos + MIN_MATCH <= block.len() {
+        let h = hash(&block[pos..]);
+        let candidate = table[h] as usize;
+        table[h] = pos as u32 + 1;
+
+        if candidate > 0 && block[candidate - 1..candidate - 1 + MIN_MATCH] == block[pos..pos + MIN_MATCH] {
+            let candidate = candidate - 1;
+            let mut len = MIN_MATCH;
+            while pos + len < block.len() && block[candidate + len] == block[pos + len] {
+                len += 1;
+            }
+            write_sequence(out, &block[literals..pos], Some(((pos - candidate) as u16, len)));
+            pos += len;
+            literals = pos;
+        } else {
+            pos += 1;
+        }
+    }
+    write_sequence(out, &block[literals..], None);
+
+    let len = out.len() - start - 4;
+    let head = if len < block.len() {
+        len as u32
+    } else {
+        out.truncate(start + 4);
+        out.extend_from_slice(block);
+        block.len() as u32 | STORED
+    };
+    LittleEndian::write_u32(&mut out[start..start + 4], head);
+}
+
+fn write_sequence(out: &mut Vec<u8>, literals: &[u8], matched: Option<(u16, usize)>) {
+    let match_len = matched.map_or(0, |(_, len)| len - MIN_MATCH);
+    out.push((literals.len().min(15) << 4 | match_len.min(15)) as u8);
+    if literals.len() >= 15 {
+        write_length(out, literals.len() - 15);
+    }
+    out.extend_from_slice(literals);
+
+    if let Some((distance, _)) = matched {
+        let mut bytes = [0; 2];
+        LittleEndian::write_u16(&mut bytes, distance);
+        out.extend_from_slice(&bytes);
+        if match_len >= 15 {
+            write_length(out, match_len - 15);
+        }
+    }
+}
+
+fn write_length(out: &mut Vec<u8>, mut n: usize) {
+    while n >= 255 {
+        out.push(255);
+        n -= 255;
+    }
+    out.push(n as u8);
+}
+
+fn read_length(data: &mut &[u8], mut n: usize) -> io::Result<usize> {
+    if n == 15 {
+        loop {
+            let (&byte, rest) = data.split_first().ok_or_else(corrupt)?;
+            *data = rest;
+            n += byte as usize;
+            if byte != 255 {
+                break
+            }
+        }
+    }
+    Ok(n)
+}
+
+/// Appends the decompressed `data` of a block to `out`
+fn decompress_block(mut data: &[u8], out: &mut Vec<u8>) -> io::Result<()> {
+    let start = out.len();
+    loop {
+        let (&token, rest) = data.split_first().ok_or_else(corrupt)?;
+        data = rest;
+
+        let literals = read_length(&mut data, (token >> 4) as usize)?;
+        if literals > data.len() {
+            return Err(corrupt())
+        }
+        out.extend_from_slice(&data[..literals]);
+        data = &data[literals..];
+        if data.is_empty() {
+            break
+        }
+
+        if data.len() < 2 {
+            return Err(corrupt())
+        }
+        let distance = LittleEndian::read_u16(data) as usize;
+        data = &data[2..];
+        let len = read_length(&mut data, (token & 15) as usize)? + MIN_MATCH;
+        if distance == 0 || distance > out.len() - start || out.len() - start + len > BLOCK_SIZE {
+            return Err(corrupt())
+        }
+
+        // The match can overlap what it's copying, so it's copied a byte at a time
+        let from = out.len() - distance;
+        for i in from..from + len {
+            let byte = out[i];
+            out.push(byte);
+        }
+    }
+
+    if out.len() - start > BLOCK_SIZE {
+        return Err(corrupt())
+    }
+    Ok(())
+}
+
+/// A `Read`er that compresses what it reads from the inner `Read`er
+pub(super) struct CompressRead<R: Read> {
+    inner: R,
+    block: Vec<u8>,
+    out: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl<R: Read> CompressRead<R> {
+    pub fn new(inner: R) -> Self {
+        CompressRead {
+            inner,
+            block: Vec::with_capacity(BLOCK_SIZE),
+            out: Vec::new(),
+            pos: 0,
+            done: false,
+        }
+    }
+}
+
+impl<R: Read> Read for CompressRead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.pos < self.out.len() {
+                let n = buf.len().min(self.out.len() - self.pos);
+                buf[..n].copy_from_slice(&self.out[self.pos..self.pos + n]);
+                self.pos += n;
+                return Ok(n)
+            }
+            if self.done || buf.is_empty() {
+                return Ok(0)
+            }
+
+            self.out.clear();
+            self.pos = 0;
+            self.block.clear();
+            (&mut self.inner).take(BLOCK_SIZE as u64).read_to_end(&mut self.block)?;
+            if self.block.is_empty() {
+                self.out.extend_from_slice(&[0; 4]);
+                self.done = true;
+            } else {
+                compress_block(&self.block, &mut self.out);
+            }
+        }
+    }
+}
+
+/// Collects written bytes into blocks and compresses them
+#[derive(Debug, Default)]
+pub(super) struct Compressor {
+    block: Vec<u8>,
+}
+
+impl Compressor {
+    /// Takes as much of `buf` as fits in the current block, compressing it into `out` once it's full
+    pub fn write(&mut self, buf: &[u8], out: &mut Vec<u8>) -> usize {
+        let n = buf.len().min(BLOCK_SIZE - self.block.len());
+        self.block.extend_from_slice(&buf[..n]);
+        if self.block.len() == BLOCK_SIZE {
+            compress_block(&self.block, out);
+            self.block.clear();
+        }
+        n
+    }
+    /// Compresses the last block into `out` and ends the stream
+    pub fn finish(self, out: &mut Vec<u8>) {
+        if !self.block.is_empty() {
+            compress_block(&self.block, out);
+        }
+        out.extend_from_slice(&[0; 4]);
+    }
+}
+
+/// Decompresses the blocks given to it as they're completed
+#[derive(Debug, Default)]
+pub(super) struct Decompressor {
+    input: Vec<u8>,
+    output: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl Decompressor {
+    /// Adds compressed bytes and decompresses the blocks that are now complete
+    pub fn push(&mut self, buf: &[u8]) -> io::Result<()> {
+        if self.done && !buf.is_empty() {
+            return Err(corrupt())
+        }
+        self.input.extend_from_slice(buf);
+
+        let mut pos = 0;
+        while self.input.len() - pos >= 4 {
+            let head = LittleEndian::read_u32(&self.input[pos..]);
+            if head == 0 {
+                self.done = true;
+                pos += 4;
+                if pos < self.input.len() {
+                    return Err(corrupt())
+                }
+                break
+            }
+
+            let len = (head & !STORED) as usize;
+            if len > BLOCK_SIZE {
+                return Err(corrupt())
+            }
+            if self.input.len() - pos - 4 < len {
+                break
+            }
+            let data = &self.input[pos + 4..pos + 4 + len];
+            if head & STORED != 0 {
+                self.output.extend_from_slice(data);
+            } else {
+                decompress_block(data, &mut self.output)?;
+            }
+            pos += 4 + len;
+        }
+        self.input.drain(..pos);
+
+        Ok(())
+    }
+    /// The decompressed bytes that haven't been consumed
+    pub fn pending(&self) -> &[u8] {
+        &self.output[self.pos..]
+    }
+    pub fn consume(&mut self, n: usize) {
+        self.pos += n;
+        if self.pos == self.output.len() {
+            self.output.clear();
+            self.pos = 0;
+        }
+    }
+    /// Fails if the end of the stream hasn't been reached
+    pub fn finish(&self) -> io::Result<()> {
+        if self.done {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the compressed data ended early"))
+        }
+    }
+    /// Reads compressed bytes from `src` until some can be decompressed into `buf`
+    pub fn read_from<R: Read>(&mut self, src: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+        let mut compressed = [0; 8 * 1024];
+        loop {
+            let pending = self.pending();
+            if !pending.is_empty() || buf.is_empty() {
+                let n = buf.len().min(pending.len());
+                buf[..n].copy_from_slice(&pending[..n]);
+                self.consume(n);
+                return Ok(n)
+            }
+
+            let n = src.read(&mut compressed)?;
+            if n == 0 {
+                self.finish()?;
+                return Ok(0)
+            }
+            self.push(&compressed[..n])?;
+        }
+    }
+}
+
+/// A `Write`r that decompresses what's written to it into the inner `Write`r
+pub(super) struct DecompressWrite<W: Write> {
+    inner: W,
+    decompressor: Decompressor,
+}
+
+impl<W: Write> DecompressWrite<W> {
+    pub fn new(inner: W) -> Self {
+        DecompressWrite {
+            inner,
+            decompressor: Decompressor::default(),
+        }
+    }
+    /// Fails if the end of the compressed stream hasn't been written
+    pub fn finish(self) -> io::Result<W> {
+        self.decompressor.finish()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for DecompressWrite<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.decompressor.push(buf)?;
+        let n = self.decompressor.pending().len();
+        self.inner.write_all(self.decompressor.pending())?;
+        self.decompressor.consume(n);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
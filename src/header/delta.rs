@@ -0,0 +1,52 @@
+//! Storing the [`Delta`] encoding in extension fields of the version 2 header
+
+use crate::{Delta, Word, Combine, MAX_ORDER, MAX_STRIDE};
+
+use super::Error::{self, InvalidHeader, Unsupported};
+use super::v2::{Header, FLAG_DELTA, EXT_ORDER, EXT_WORD, EXT_STRIDE, EXT_COMBINE};
+
+/// Returns the extension fields for the parts of `delta` that differ from the plain encoding
+pub(super) fn to_extensions(delta: &Delta) -> Vec<(u8, Vec<u8>)> {
+    let mut extensions = Vec::new();
+    if delta.order() != 1 {
+        extensions.push((EXT_ORDER, vec![delta.order()]));
+    }
+    let word = delta.word();
+    if word != Word::U8 {
+        extensions.push((EXT_WORD, vec![word.size() as u8, word.is_big_endian() as u8]));
+    }
+    if delta.stride() != 1 {
+        extensions.push((EXT_STRIDE, vec![delta.stride()]));
+    }
+    if delta.combine() == Combine::Xor {
+        extensions.push((EXT_COMBINE, vec![1]));
+    }
+    extensions
+}
+
+/// Reads the delta encoding the header says the payload has
+///
+/// Without [`FLAG_DELTA`], it's the plain encoding and the extension fields are ignored.
+pub(super) fn from_header(header: &Header) -> Result<Delta, Error> {
+    if !header.has_flag(FLAG_DELTA) {
+        return Ok(Delta::new())
+    }
+
+    let mut order = 1;
+    let mut word = Word::U8;
+    let mut stride = 1;
+    let mut combine = Combine::Add;
+    for &(tag, ref data) in &header.extensions {
+        match (tag, &**data) {
+            (EXT_ORDER, &[n]) if (1..=MAX_ORDER).contains(&n) => order = n,
+            (EXT_WORD, &[size, endian @ 0..=1]) => word = Word::from_parts(size, endian == 1).ok_or(Unsupported)?,
+            (EXT_STRIDE, &[n]) if (1..=MAX_STRIDE).contains(&n) => stride = n,
+            (EXT_COMBINE, &[0]) => combine = Combine::Add,
+            (EXT_COMBINE, &[1]) => combine = Combine::Xor,
+            (EXT_ORDER, &[_]) | (EXT_WORD, &[_, _]) | (EXT_STRIDE, &[_]) | (EXT_COMBINE, &[_]) => return Err(Unsupported),
+            (EXT_ORDER, _) | (EXT_WORD, _) | (EXT_STRIDE, _) | (EXT_COMBINE, _) => return Err(InvalidHeader),
+            _ => (),
+        }
+    }
+    Ok(Delta::with_order(order).with_word(word).with_stride(stride).with_combine(combine))
+}
@@ -6,28 +6,49 @@ use siphasher::sip::SipHasher;
 #[derive(Debug, Clone)]
 pub struct HashingWrite<T: Write> {
     hasher: SipHasher,
+    count: u64,
     inner: T,
 }
 
 impl<T: Write> HashingWrite<T> {
     #[inline]
     pub fn new(writer: T) -> Self {
+        Self::with_hasher(writer, SipHasher::new())
+    }
+    #[inline]
+    pub fn with_hasher(writer: T, hasher: SipHasher) -> Self {
         Self {
             inner: writer,
-            hasher: SipHasher::new(),
+            count: 0,
+            hasher,
         }
     }
+    /// The number of bytes that have been hashed
+    #[inline]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+    /// Bytes written directly to the inner `Write`r aren't hashed
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
     #[inline]
     pub fn into_inner(self) -> (T, u64) {
         let Self{inner, ..} = self;
         (inner, self.hasher.finish())
     }
+    #[inline]
+    pub fn into_parts(self) -> (T, SipHasher) {
+        (self.inner, self.hasher)
+    }
 }
 impl<T: Write> Write for HashingWrite<T> {
     #[inline]
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
         let n = self.inner.write(buf)?;
         self.hasher.write(&buf[..n]);
+        self.count += n as u64;
 
         Ok(n)
     }
@@ -40,28 +61,48 @@ impl<T: Write> Write for HashingWrite<T> {
 #[derive(Debug, Clone)]
 pub struct HashingRead<T: Read> {
     hasher: SipHasher,
+    count: u64,
     inner: T,
 }
 
 impl<T: Read> HashingRead<T> {
     #[inline]
     pub fn new(reader: T) -> Self {
+        Self::with_hasher(reader, SipHasher::new())
+    }
+    #[inline]
+    pub fn with_hasher(reader: T, hasher: SipHasher) -> Self {
         Self {
             inner: reader,
-            hasher: SipHasher::new(),
+            count: 0,
+            hasher,
         }
     }
+    /// The number of bytes that have been hashed
+    #[inline]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+    #[inline]
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
     #[inline]
     pub fn into_inner(self) -> (T, u64) {
         let Self{inner, ..} = self;
         (inner, self.hasher.finish())
     }
+    #[inline]
+    pub fn hasher(&self) -> &SipHasher {
+        &self.hasher
+    }
 }
 impl<T: Read> Read for HashingRead<T> {
     #[inline]
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
         let n = self.inner.read(buf)?;
         self.hasher.write(&buf[..n]);
+        self.count += n as u64;
 
         Ok(n)
     }
@@ -0,0 +1,120 @@
+use std::io::Read;
+
+use crate::Delta;
+
+use super::v2::{Algorithm, FLAG_CHECKSUM, FLAG_AUTH, FLAG_TRAILER, FLAG_CHUNKED, FLAG_LENGTH, FLAG_COMPRESSED};
+use super::armor;
+use super::{Error, Kdf, Metadata, Parsed, parse, chunked};
+
+/// Which kind of header a file starts with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    /// `ΔL`, unsalted with a checksum
+    Checksum,
+    /// `Δl`, unsalted without a checksum
+    NoChecksum,
+    /// `ΔK`, salted with a checksum
+    SaltedChecksum,
+    /// `Δk`, salted without a checksum
+    SaltedNoChecksum,
+    /// `ΔV`, the version 2 header
+    V2,
+}
+
+/// What a header says about the file, as returned by [`read_header`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct HeaderInfo {
+    /// Which kind of header it is
+    pub variant: Variant,
+    /// The length of the header in bytes, which is where the payload starts
+    pub header_len: u64,
+    /// The raw flags, the legacy headers are given the flags of the version 2 header that means the same
+    pub flags: u16,
+    /// Whether the plaintext length is stored
+    pub has_length: bool,
+    /// The stored plaintext length, unless it's in the trailer
+    pub length: Option<u64>,
+    /// Whether the plaintext checksum is stored
+    pub has_checksum: bool,
+    /// The stored checksum, unless it's in the trailer
+    pub checksum: Option<u64>,
+    /// Whether an authentication tag is stored
+    pub authenticated: bool,
+    /// The stored authentication tag, unless it's in the trailer
+    pub tag: Option<u64>,
+    /// Whether the length, checksum and authentication tag are in a trailer after the payload
+    pub trailer: bool,
+    /// Whether the plaintext was compressed before it was encoded
+    pub compressed: bool,
+    /// The delta encoding of the payload
+    pub delta: Delta,
+    /// The size of the chunks, if the payload is chunked
+    pub chunk_size: Option<u32>,
+    /// How the offsets are derived from a passphrase, if they are
+    pub kdf: Option<Kdf>,
+    /// The salt for the passphrase
+    pub salt: Option<[u8; 16]>,
+    /// The metadata of the original file, if it was stored
+    pub metadata: Metadata,
+    /// The extension fields of the version 2 header as tag and data
+    pub extensions: Vec<(u8, Vec<u8>)>,
+}
+
+/// Reads the header from `src` without decoding anything
+///
+/// `src` is left at the start of the payload, unless it's armored.
+/// The armor is detected, and `header_len` is then the length of the header in the data inside it.
+pub fn read_header<R: Read>(src: &mut R) -> Result<HeaderInfo, Error> {
+    // Counts the bytes read by how much of the limit is used up
+    let mut src = armor::unarmor(src)?.take(u64::MAX);
+    let parsed = parse(&mut src)?;
+    let header_len = u64::MAX - src.limit();
+
+    let chunk_size = if parsed.header.has_flag(FLAG_CHUNKED) {
+        Some(chunked::chunk_size(&parsed)?)
+    } else {
+        None
+    };
+
+    Ok(HeaderInfo::new(parsed, header_len, chunk_size))
+}
+
+impl HeaderInfo {
+    fn new(parsed: Parsed, header_len: u64, chunk_size: Option<u32>) -> Self {
+        let Parsed{magic, header, delta, length, checksum, tag} = parsed;
+
+        let variant = match magic {
+            b'L' => Variant::Checksum,
+            b'l' => Variant::NoChecksum,
+            b'K' => Variant::SaltedChecksum,
+            b'k' => Variant::SaltedNoChecksum,
+            _ => Variant::V2,
+        };
+        let (kdf, salt) = match header.algorithm {
+            Algorithm::Offsetter => (None, None),
+            Algorithm::PassHash{salt, rounds} => (Some(Kdf::PassHash{rounds}), Some(salt)),
+            Algorithm::Keystream{salt, rounds} => (Some(Kdf::Keystream{rounds}), Some(salt)),
+        };
+
+        HeaderInfo {
+            variant,
+            header_len,
+            flags: header.flags,
+            has_length: header.has_flag(FLAG_LENGTH),
+            length,
+            has_checksum: header.has_flag(FLAG_CHECKSUM),
+            checksum,
+            authenticated: header.has_flag(FLAG_AUTH),
+            tag,
+            trailer: header.has_flag(FLAG_TRAILER),
+            compressed: header.has_flag(FLAG_COMPRESSED),
+            delta,
+            chunk_size,
+            metadata: Metadata::from_extensions(&header.extensions),
+            kdf,
+            salt,
+            extensions: header.extensions,
+        }
+    }
+}
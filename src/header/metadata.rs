@@ -0,0 +1,151 @@
+use byteorder::{LittleEndian, ByteOrder};
+
+use std::io;
+use std::fs::{self, File};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::v2::{EXT_NAME, EXT_SIZE, EXT_MODIFIED, EXT_MODE};
+
+/// Metadata of the original file, stored in extension fields of the version 2 header
+///
+/// Note that it is stored unencrypted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    /// The file name, without any directories
+    pub name: Option<String>,
+    /// The size in bytes
+    pub size: Option<u64>,
+    /// The last modification time
+    pub modified: Option<SystemTime>,
+    /// The Unix permission bits
+    ///
+    /// Only the read, write and execute bits are stored and restored.
+    /// The setuid, setgid and sticky bits would come from the unauthenticated header, so they're always dropped.
+    pub mode: Option<u32>,
+}
+
+/// The permission bits that are stored and restored
+const MODE_BITS: u32 = 0o777;
+
+impl Metadata {
+    /// Reads the metadata of the file at `path`
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let metadata = fs::metadata(path)?;
+
+        Ok(Metadata {
+            name: path.file_name().and_then(|name| name.to_str()).map(ToOwned::to_owned),
+            size: Some(metadata.len()),
+            modified: metadata.modified().ok(),
+            mode: Some(mode(&metadata.permissions())),
+        })
+    }
+    /// Sets the modification time and permissions of `file` to the stored ones
+    ///
+    /// Only the read, write and execute bits of the mode are set.
+    /// Outside of Unix, only whether the file is read-only is set from the permissions.
+    pub fn apply(&self, file: &File) -> io::Result<()> {
+        if let Some(modified) = self.modified {
+            file.set_modified(modified)?;
+        }
+        if let Some(mode) = self.mode {
+            let mut permissions = file.metadata()?.permissions();
+            set_mode(&mut permissions, mode);
+            file.set_permissions(permissions)?;
+        }
+        Ok(())
+    }
+    /// Returns the extension fields holding the metadata
+    pub(super) fn to_extensions(&self) -> Vec<(u8, Vec<u8>)> {
+        let mut extensions = Vec::new();
+
+        if let Some(ref name) = self.name {
+            let mut name = name.as_bytes();
+            // Names that are too long are cut at a character boundary
+            let mut len = name.len().min(u16::MAX as usize);
+            while std::str::from_utf8(&name[..len]).is_err() {
+                len -= 1;
+            }
+            name = &name[..len];
+            extensions.push((EXT_NAME, name.to_vec()));
+        }
+        if let Some(size) = self.size {
+            let mut data = vec![0; 8];
+            LittleEndian::write_u64(&mut data, size);
+            extensions.push((EXT_SIZE, data));
+        }
+        if let Some(modified) = self.modified {
+            let (secs, nanos) = match modified.duration_since(UNIX_EPOCH) {
+                Ok(after) => (after.as_secs() as i64, after.subsec_nanos()),
+                Err(e) => {
+                    // Before the epoch, the nanoseconds still count forwards
+                    let before = e.duration();
+                    let secs = -(before.as_secs() as i64);
+                    match before.subsec_nanos() {
+                        0 => (secs, 0),
+                        nanos => (secs - 1, 1_000_000_000 - nanos),
+                    }
+                }
+            };
+            let mut data = vec![0; 12];
+            LittleEndian::write_i64(&mut data[..8], secs);
+            LittleEndian::write_u32(&mut data[8..], nanos);
+            extensions.push((EXT_MODIFIED, data));
+        }
+        if let Some(mode) = self.mode {
+            let mut data = vec![0; 4];
+            LittleEndian::write_u32(&mut data, mode & MODE_BITS);
+            extensions.push((EXT_MODE, data));
+        }
+
+        extensions
+    }
+    /// Reads the metadata from the extension fields, ignoring fields that are malformed
+    pub(super) fn from_extensions(extensions: &[(u8, Vec<u8>)]) -> Self {
+        let mut metadata = Metadata::default();
+
+        for &(tag, ref data) in extensions {
+            match (tag, data.len()) {
+                (EXT_NAME, _) => metadata.name = String::from_utf8(data.clone()).ok(),
+                (EXT_SIZE, 8) => metadata.size = Some(LittleEndian::read_u64(data)),
+                (EXT_MODIFIED, 12) => {
+                    let secs = LittleEndian::read_i64(&data[..8]);
+                    let nanos = Duration::from_nanos(u64::from(LittleEndian::read_u32(&data[8..])));
+                    metadata.modified = if secs >= 0 {
+                        UNIX_EPOCH.checked_add(Duration::from_secs(secs as u64) + nanos)
+                    } else {
+                        UNIX_EPOCH.checked_sub(Duration::from_secs(secs.unsigned_abs()))
+                            .and_then(|time| time.checked_add(nanos))
+                    };
+                }
+                (EXT_MODE, 4) => metadata.mode = Some(LittleEndian::read_u32(data)),
+                _ => (),
+            }
+        }
+
+        metadata
+    }
+}
+
+#[cfg(unix)]
+fn mode(permissions: &fs::Permissions) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    permissions.mode() & MODE_BITS
+}
+
+#[cfg(not(unix))]
+fn mode(permissions: &fs::Permissions) -> u32 {
+    if permissions.readonly() { 0o444 } else { 0o644 }
+}
+
+#[cfg(unix)]
+fn set_mode(permissions: &mut fs::Permissions, mode: u32) {
+    use std::os::unix::fs::PermissionsExt;
+    permissions.set_mode(mode & MODE_BITS);
+}
+
+#[cfg(not(unix))]
+fn set_mode(permissions: &mut fs::Permissions, mode: u32) {
+    permissions.set_readonly(mode & 0o222 == 0);
+}
@@ -0,0 +1,298 @@
+use std::io::{self, Read, Write};
+use std::hash::Hasher;
+
+use siphasher::sip::SipHasher;
+
+use crate::{Offset, DeltaWriter, DeltaReader};
+
+use super::hashing_io::{HashingRead, HashingWrite};
+use super::chunked::{self, ChunksRead};
+use super::trailer::TrailerRead;
+use super::v2::{Algorithm, FLAG_CHECKSUM, FLAG_AUTH, FLAG_CHUNKED};
+#[cfg(feature = "compress")]
+use super::v2::FLAG_COMPRESSED;
+#[cfg(feature = "compress")]
+use super::compress::{Compressor, Decompressor};
+use super::{Error, Kdf, Options, Parsed, PassphraseOffsetter, parse, check_given, check_key, check_length, check_summary,
+    derive_offsetter, auth_hasher, finish_mac, tag_bytes};
+
+/// A `Write`r that encodes everything written to it with the version 2 header
+///
+/// The header is written before the first bytes, and the length, checksum and authentication tag
+/// are written in a trailer by [`finish`](Encoder::finish), so the inner `Write`r doesn't need to be seekable.
+/// Dropping it without calling `finish` leaves the trailer out, which makes the output fail to decode.
+#[derive(Debug)]
+pub struct Encoder<W: Write, O: Offset = PassphraseOffsetter> {
+    /// The header, until it has been written
+    head: Option<Vec<u8>>,
+    inner: DeltaWriter<HashingWrite<W>, O>,
+    /// Hashes the plaintext, if a checksum is stored
+    checksum: Option<SipHasher>,
+    #[cfg(feature = "compress")]
+    compressor: Option<Compressor>,
+    flags: u16,
+}
+
+impl<W: Write, O: Offset> Encoder<W, O> {
+    /// Makes an `Encoder` with the given offsetter, which has to be given to [`Decoder::new`] as well
+    ///
+    /// Fails with [`Error::Unsupported`] if the options need a key the offsetter doesn't have.
+    pub fn new(writer: W, offsetter: O, options: &Options) -> Result<Self, Error> {
+        Self::with_header(writer, offsetter, options, Algorithm::Offsetter)
+    }
+    fn with_header(writer: W, offsetter: O, options: &Options, algorithm: Algorithm) -> Result<Self, Error> {
+        let header = options.header(&offsetter, algorithm, true);
+        let head = header.to_bytes();
+        let mac = if header.has_flag(FLAG_AUTH) { auth_hasher(&offsetter, &head)? } else { SipHasher::new() };
+
+        Ok(Encoder {
+            head: Some(head),
+            inner: DeltaWriter::with_delta(HashingWrite::with_hasher(writer, mac), offsetter, options.delta),
+            checksum: if header.has_flag(FLAG_CHECKSUM) { Some(SipHasher::new()) } else { None },
+            #[cfg(feature = "compress")]
+            compressor: if header.has_flag(FLAG_COMPRESSED) { Some(Compressor::default()) } else { None },
+            flags: header.flags,
+        })
+    }
+    fn write_header(&mut self) -> io::Result<()> {
+        if let Some(ref head) = self.head {
+            self.inner.get_mut().get_mut().write_all(head)?;
+            self.head = None;
+        }
+        Ok(())
+    }
+    /// Encodes and hashes all of `buf`
+    #[cfg(feature = "compress")]
+    fn encode_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.inner.write_all(buf)?;
+        if let Some(ref mut checksum) = self.checksum {
+            checksum.write(buf);
+        }
+        Ok(())
+    }
+    /// Writes the header, if nothing has been written, and the trailer and returns the inner `Write`r
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_header()?;
+        #[cfg(feature = "compress")]
+        {
+            if let Some(compressor) = self.compressor.take() {
+                let mut compressed = Vec::new();
+                compressor.finish(&mut compressed);
+                self.encode_all(&compressed)?;
+            }
+        }
+
+        let hashing = self.inner.finish()?;
+        let len = hashing.count();
+        let (mut inner, mac) = hashing.into_parts();
+        let checksum = self.checksum.map_or(0, |hasher| hasher.finish());
+        let mac = if self.flags & FLAG_AUTH != 0 { Some(mac) } else { None };
+
+        inner.write_all(&tag_bytes(self.flags, len, checksum, mac))?;
+        inner.flush()?;
+        Ok(inner)
+    }
+}
+
+impl<W: Write> Encoder<W> {
+    /// Makes an `Encoder` with offsets derived from the passphrase with a random salt
+    ///
+    /// Decode it with [`Decoder::with_passphrase`] or [`decode_with_passphrase`](super::decode_with_passphrase).
+    pub fn with_passphrase(writer: W, passphrase: &str, kdf: Kdf, options: &Options) -> Result<Self, Error> {
+        let (offsetter, algorithm) = derive_offsetter(passphrase, kdf)?;
+        Self::with_header(writer, offsetter, options, algorithm)
+    }
+}
+
+impl<W: Write, O: Offset> Write for Encoder<W, O> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_header()?;
+        #[cfg(feature = "compress")]
+        {
+            if let Some(ref mut compressor) = self.compressor {
+                let mut compressed = Vec::new();
+                let n = compressor.write(buf, &mut compressed);
+                self.encode_all(&compressed)?;
+                return Ok(n)
+            }
+        }
+
+        let n = self.inner.write(buf)?;
+        if let Some(ref mut checksum) = self.checksum {
+            checksum.write(&buf[..n]);
+        }
+        Ok(n)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_header()?;
+        self.inner.flush()
+    }
+}
+
+/// Where the offsetter of a [`Decoder`] comes from, before the header has been read
+enum Pending<O> {
+    Offsetter(O),
+    Passphrase(String, fn(&str, &Parsed) -> O),
+}
+
+enum State<R: Read, O: Offset> {
+    Header(R, Pending<O>),
+    Payload(Box<Payload<R, O>>),
+    Chunks(Box<ChunksRead<R, O>>),
+    Done,
+    /// Reading the header failed
+    Poisoned,
+}
+
+struct Payload<R: Read, O: Offset> {
+    inner: DeltaReader<HashingRead<TrailerRead<R>>, O>,
+    parsed: Parsed,
+    /// Hashes the plaintext
+    hasher: SipHasher,
+}
+
+/// A `Read`er that decodes a stream made by any of the encoders in this module
+///
+/// The header is read on the first read.
+/// Header errors, and a checksum or authentication tag that doesn't match at the end,
+/// are returned as `io::Error`s wrapping the [`Error`].
+/// Unlike [`decode_verified`](super::decode_verified), the plaintext is returned before it can be checked,
+/// so it shouldn't be trusted until the `Decoder` has returned the end of the stream.
+pub struct Decoder<R: Read, O: Offset = PassphraseOffsetter> {
+    state: State<R, O>,
+    /// Decompresses the payload, if it's compressed
+    #[cfg(feature = "compress")]
+    decompressor: Option<Decompressor>,
+}
+
+impl<R: Read, O: Offset> Decoder<R, O> {
+    /// Makes a `Decoder` that uses the given offsetter
+    ///
+    /// Headers that derive their offsets from a passphrase fail with [`Error::PassphraseRequired`].
+    pub fn new(reader: R, offsetter: O) -> Self {
+        Decoder {
+            state: State::Header(reader, Pending::Offsetter(offsetter)),
+            #[cfg(feature = "compress")]
+            decompressor: None,
+        }
+    }
+}
+
+impl<R: Read> Decoder<R> {
+    /// Makes a `Decoder` that derives the offsets from `passphrase` like the header says
+    pub fn with_passphrase(reader: R, passphrase: &str) -> Self {
+        Decoder {
+            state: State::Header(reader, Pending::Passphrase(passphrase.to_owned(), PassphraseOffsetter::from_header)),
+            #[cfg(feature = "compress")]
+            decompressor: None,
+        }
+    }
+}
+
+impl<R: Read, O: Offset> Decoder<R, O> {
+    fn read_header(&mut self) -> Result<(), Error> {
+        let (mut reader, pending) = match std::mem::replace(&mut self.state, State::Poisoned) {
+            State::Header(reader, pending) => (reader, pending),
+            state => {
+                self.state = state;
+                return Ok(())
+            }
+        };
+
+        let parsed = parse(&mut reader)?;
+        let offsetter = match pending {
+            Pending::Offsetter(offsetter) => {
+                check_given(&offsetter, &parsed)?;
+                offsetter
+            }
+            Pending::Passphrase(passphrase, derive) => {
+                let offsetter = derive(&passphrase, &parsed);
+                check_key(&offsetter, &parsed)?;
+                offsetter
+            }
+        };
+
+        if parsed.header.has_flag(FLAG_CHUNKED) {
+            self.state = State::Chunks(Box::new(ChunksRead::new(reader, offsetter, parsed.delta, chunked::chunk_size(&parsed)?)));
+            return Ok(())
+        }
+
+        let mac = if parsed.header.has_flag(FLAG_AUTH) {
+            auth_hasher(&offsetter, &parsed.header.to_bytes())?
+        } else {
+            SipHasher::new()
+        };
+        let trailer_len = parsed.trailer_len();
+        #[cfg(feature = "compress")]
+        {
+            if parsed.header.has_flag(FLAG_COMPRESSED) {
+                self.decompressor = Some(Decompressor::default());
+            }
+        }
+
+        self.state = State::Payload(Box::new(Payload {
+            inner: DeltaReader::with_delta(HashingRead::with_hasher(TrailerRead::new(reader, trailer_len), mac), offsetter, parsed.delta),
+            parsed,
+            hasher: SipHasher::new(),
+        }));
+        Ok(())
+    }
+}
+
+impl<R: Read, O: Offset> Payload<R, O> {
+    /// Checks the length, checksum and authentication tag once the end has been reached
+    fn check(&self) -> Result<(), Error> {
+        let mut parsed = self.parsed.clone();
+        if parsed.trailer_len() > 0 {
+            parsed.read_tags(self.inner.get_ref().get_ref().trailer()?);
+        }
+        let len = self.inner.get_ref().count();
+
+        check_length(&parsed, len)?;
+        if let Some(tag) = parsed.tag {
+            if finish_mac(*self.inner.get_ref().hasher(), parsed.length, parsed.checksum) != tag {
+                return Err(Error::AuthenticationFailed)
+            }
+        }
+        check_summary(&parsed, len, self.hasher.finish())
+    }
+}
+
+impl<R: Read, O: Offset> Read for Payload<R, O> {
+    /// Reads the delta decoded payload and checks it at the end
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.write(&buf[..n]);
+
+        if n == 0 && !buf.is_empty() {
+            self.check()?;
+        }
+        Ok(n)
+    }
+}
+
+impl<R: Read, O: Offset> Read for Decoder<R, O> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read_header()?;
+
+        let payload = match self.state {
+            State::Payload(ref mut payload) => payload,
+            State::Chunks(ref mut chunks) => return chunks.read(buf),
+            State::Done => return Ok(0),
+            State::Header(..) | State::Poisoned => return Err(io::Error::other("the header couldn't be read")),
+        };
+
+        #[cfg(feature = "compress")]
+        let n = match self.decompressor {
+            Some(ref mut decompressor) => decompressor.read_from(payload, buf)?,
+            None => payload.read(buf)?,
+        };
+        #[cfg(not(feature = "compress"))]
+        let n = payload.read(buf)?;
+
+        if n == 0 && !buf.is_empty() {
+            self.state = State::Done;
+        }
+        Ok(n)
+    }
+}
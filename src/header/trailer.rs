@@ -0,0 +1,62 @@
+use std::io::{Result, Read, Error, ErrorKind};
+
+/// A `Read`er that holds back the last `len` bytes of the inner `Read`er
+///
+/// Once it has returned EOF, the held back bytes are the trailer.
+#[derive(Debug, Clone)]
+pub struct TrailerRead<T: Read> {
+    inner: T,
+    buf: Vec<u8>,
+    len: usize,
+    eof: bool,
+}
+
+/// How much is read from the inner `Read`er at once
+const CHUNK_SIZE: usize = 8192;
+
+impl<T: Read> TrailerRead<T> {
+    #[inline]
+    pub fn new(reader: T, len: usize) -> Self {
+        Self {
+            inner: reader,
+            buf: Vec::with_capacity(len + CHUNK_SIZE),
+            len,
+            eof: false,
+        }
+    }
+    /// Returns the trailer, if the end has been reached
+    ///
+    /// Fails if the inner `Read`er ended before there was a whole trailer.
+    pub fn trailer(&self) -> Result<&[u8]> {
+        debug_assert!(self.eof);
+        if self.buf.len() == self.len {
+            Ok(&self.buf)
+        } else {
+            Err(Error::new(ErrorKind::UnexpectedEof, "the stream ended before the trailer"))
+        }
+    }
+}
+
+impl<T: Read> Read for TrailerRead<T> {
+    fn read(&mut self, out: &mut [u8]) -> Result<usize> {
+        loop {
+            if self.buf.len() > self.len {
+                let n = (self.buf.len() - self.len).min(out.len());
+                out[..n].copy_from_slice(&self.buf[..n]);
+                self.buf.drain(..n);
+                return Ok(n)
+            }
+            if self.eof || out.is_empty() {
+                return Ok(0)
+            }
+
+            let old_len = self.buf.len();
+            self.buf.resize(old_len + CHUNK_SIZE, 0);
+            let res = self.inner.read(&mut self.buf[old_len..]);
+            self.buf.truncate(old_len + *res.as_ref().unwrap_or(&0));
+            if res? == 0 {
+                self.eof = true;
+            }
+        }
+    }
+}
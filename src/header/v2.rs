@@ -0,0 +1,210 @@
+//! The version 2 header (`ΔV\n`)
+//!
+//! After the magic bytes, the header contains, in order:
+//!
+//! - the format version (`u8`, currently 2)
+//! - the offsetter id (`u8`), see [`Algorithm`]
+//! - flags (`u16`), see the `FLAG_*` constants
+//! - the salt (16 bytes) and rounds (`u32`), only for offsetters derived from a passphrase
+//! - extension fields, each a tag (`u8`), a length (`u16`) and that many bytes, ended by a 0 tag
+//! - the plaintext length (`u64`), only if [`FLAG_LENGTH`] is set
+//! - the checksum (`u64`), only if [`FLAG_CHECKSUM`] is set
+//! - the authentication tag (`u64`), only if [`FLAG_AUTH`] is set
+//!
+//! If [`FLAG_TRAILER`] is set, the length, checksum and authentication tag come after the payload instead.
+//!
+//! All integers are little endian.
+//! Flags change how the payload has to be decoded, so unknown flags make the header unsupported,
+//! whereas unknown extension fields are skipped.
+
+use byteorder::{LittleEndian, ByteOrder};
+
+use std::io::{self, Read, Write};
+
+use crate::MAX_ROUNDS;
+
+use super::Error::{self, Unsupported};
+
+/// The magic bytes of the version 2 header
+pub(super) const MAGIC: &[u8; 4] = b"\xCE\x94V\n";
+
+const VERSION: u8 = 2;
+
+/// The plaintext checksum is stored at the end of the header
+pub(crate) const FLAG_CHECKSUM: u16 = 1;
+
+/// A keyed SipHash of the header, the ciphertext, the length and the checksum is stored at the end of the header
+pub(crate) const FLAG_AUTH: u16 = 2;
+
+/// The checksum and authentication tag are stored after the payload instead of in the header
+pub(crate) const FLAG_TRAILER: u16 = 4;
+
+/// The payload is split into chunks with their own checksums, see the `chunked` module
+pub(crate) const FLAG_CHUNKED: u16 = 8;
+
+/// The length of the plaintext (`u64`) is stored before the checksum
+pub(crate) const FLAG_LENGTH: u16 = 16;
+
+/// The plaintext is compressed before it's encoded, as described in the `compress` module
+///
+/// The length and checksum are those of the compressed plaintext.
+/// Only supported with the `compress` feature.
+pub(crate) const FLAG_COMPRESSED: u16 = 32;
+
+/// The payload isn't delta encoded the plain way, the extension fields from [`EXT_ORDER`] on say how it is
+pub(crate) const FLAG_DELTA: u16 = 64;
+
+const KNOWN_FLAGS: u16 = FLAG_CHECKSUM | FLAG_AUTH | FLAG_TRAILER | FLAG_CHUNKED | FLAG_LENGTH | FLAG_DELTA
+    | if cfg!(feature = "compress") { FLAG_COMPRESSED } else { 0 };
+
+/// Extension field holding a check value of the offsetter's key (4 bytes)
+pub(crate) const EXT_KEY_CHECK: u8 = 1;
+
+/// Extension field holding the chunk size (`u32`), required by [`FLAG_CHUNKED`]
+pub(crate) const EXT_CHUNK_SIZE: u8 = 2;
+
+/// Extension field holding the original file name (UTF-8)
+pub(crate) const EXT_NAME: u8 = 3;
+
+/// Extension field holding the original file size (`u64`)
+pub(crate) const EXT_SIZE: u8 = 4;
+
+/// Extension field holding the modification time as seconds since the Unix epoch (`i64`) and nanoseconds (`u32`)
+pub(crate) const EXT_MODIFIED: u8 = 5;
+
+/// Extension field holding the Unix permission bits (`u32`)
+pub(crate) const EXT_MODE: u8 = 6;
+
+/// Extension field holding the order of the delta encoding (`u8`), 1 if it's missing
+pub(crate) const EXT_ORDER: u8 = 7;
+
+/// Extension field holding the size of the delta encoded words in bytes (`u8`) and 1 if they're big endian or else 0 (`u8`),
+/// single bytes if it's missing
+pub(crate) const EXT_WORD: u8 = 8;
+
+/// Extension field holding the number of interleaved channels of the delta encoding (`u8`), 1 if it's missing
+pub(crate) const EXT_STRIDE: u8 = 9;
+
+/// Extension field holding how the delta encoding combines bytes (`u8`), 0 for addition and 1 for XOR,
+/// addition if it's missing
+pub(crate) const EXT_COMBINE: u8 = 10;
+
+/// Extension tags from this value and up are free to be used by applications
+pub const FIRST_APPLICATION_TAG: u8 = 0x80;
+
+/// How the offsets are made
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Algorithm {
+    /// The offsetter is given by whoever decodes it (id 0)
+    Offsetter,
+    /// [`PassHashOffsetter::derive`](crate::PassHashOffsetter::derive) (id 1)
+    PassHash {
+        salt: [u8; 16],
+        rounds: u32,
+    },
+    /// [`KeystreamOffsetter::derive`](crate::KeystreamOffsetter::derive) (id 2)
+    Keystream {
+        salt: [u8; 16],
+        rounds: u32,
+    },
+}
+
+/// The fields of a version 2 header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Header {
+    pub algorithm: Algorithm,
+    pub flags: u16,
+    pub extensions: Vec<(u8, Vec<u8>)>,
+}
+
+impl Header {
+    /// Returns the magic bytes followed by the header, except for the checksum and tag
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        self.write(&mut bytes).expect("writing to a Vec can't fail");
+        bytes
+    }
+    /// Writes the header after the magic bytes, except for the checksum
+    pub fn write<W: Write>(&self, dest: &mut W) -> io::Result<()> {
+        let mut fixed = [VERSION, 0, 0, 0];
+        LittleEndian::write_u16(&mut fixed[2..], self.flags);
+
+        match self.algorithm {
+            Algorithm::Offsetter => dest.write_all(&fixed)?,
+            Algorithm::PassHash{salt, rounds} | Algorithm::Keystream{salt, rounds} => {
+                fixed[1] = if let Algorithm::PassHash{..} = self.algorithm { 1 } else { 2 };
+                dest.write_all(&fixed)?;
+                dest.write_all(&salt)?;
+                let mut rounds_bytes = [0; 4];
+                LittleEndian::write_u32(&mut rounds_bytes, rounds);
+                dest.write_all(&rounds_bytes)?;
+            }
+        }
+
+        for &(tag, ref data) in &self.extensions {
+            debug_assert_ne!(tag, 0);
+            debug_assert!(data.len() <= u16::MAX as usize);
+
+            let mut tag_len = [tag, 0, 0];
+            LittleEndian::write_u16(&mut tag_len[1..], data.len() as u16);
+            dest.write_all(&tag_len)?;
+            dest.write_all(data)?;
+        }
+        dest.write_all(&[0])
+    }
+    /// Reads the header after the magic bytes, except for the checksum
+    pub fn read<R: Read>(src: &mut R) -> Result<Self, Error> {
+        let mut fixed = [0; 4];
+        src.read_exact(&mut fixed)?;
+        let flags = LittleEndian::read_u16(&fixed[2..]);
+
+        if fixed[0] != VERSION || flags & !KNOWN_FLAGS != 0 {
+            return Err(Unsupported)
+        }
+
+        let algorithm = match fixed[1] {
+            0 => Algorithm::Offsetter,
+            id @ 1..=2 => {
+                let mut salt = [0; 16];
+                src.read_exact(&mut salt)?;
+                let mut rounds = [0; 4];
+                src.read_exact(&mut rounds)?;
+                let rounds = LittleEndian::read_u32(&rounds);
+                if rounds > MAX_ROUNDS {
+                    return Err(Unsupported)
+                }
+
+                if id == 1 {
+                    Algorithm::PassHash{salt, rounds}
+                } else {
+                    Algorithm::Keystream{salt, rounds}
+                }
+            }
+            _ => return Err(Unsupported),
+        };
+
+        let mut extensions = Vec::new();
+        loop {
+            let mut tag = [0];
+            src.read_exact(&mut tag)?;
+            if tag[0] == 0 {
+                break
+            }
+            let mut len = [0; 2];
+            src.read_exact(&mut len)?;
+            let mut data = vec![0; LittleEndian::read_u16(&len) as usize];
+            src.read_exact(&mut data)?;
+            extensions.push((tag[0], data));
+        }
+
+        Ok(Header {
+            algorithm,
+            flags,
+            extensions,
+        })
+    }
+    #[inline]
+    pub fn has_flag(&self, flag: u16) -> bool {
+        self.flags & flag != 0
+    }
+}
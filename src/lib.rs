@@ -3,14 +3,18 @@
 
 use byteorder::{LittleEndian, ByteOrder};
 
-use std::io::{Result, Write, Read};
+use std::io::{Result, Write, Read, BufRead, Seek, SeekFrom, Error, ErrorKind};
 
 use std::hash::Hasher;
 use siphasher::sip::SipHasher;
+use siphasher::sip128::{self, Hasher128, Hash128};
 
+mod delta;
 pub mod header;
+mod simd;
 
-pub use crate::header::{decode, encode_no_checksum, encode_with_checksum};
+pub use crate::delta::{Delta, Word, Combine, MAX_ORDER, MAX_STRIDE};
+pub use crate::header::{decode, decode_with_passphrase, decode_verified, encode_no_checksum, encode_with_checksum};
 
 /// Offsets for delta-l
 pub trait Offset {
@@ -23,45 +27,306 @@ pub trait Offset {
     fn step_back(&mut self);
     /// Reset the state of the offsetter
     fn reset(&mut self);
+    /// Advance the state `n` steps, as if `next_offset` had been called `n` times
+    fn skip(&mut self, n: u64) {
+        for _ in 0..n {
+            self.next_offset();
+        }
+    }
+    /// Returns how many offsets have been returned since the last reset,
+    /// if the offsetter keeps track of it
+    #[inline]
+    fn position(&self) -> Option<u64> {
+        None
+    }
+    /// Sets the state to what it would be after `pos` calls to `next_offset` after a reset
+    fn set_position(&mut self, pos: u64) {
+        self.reset();
+        self.skip(pos);
+    }
+    /// Returns the secret the offsets are made from, if there is one
+    ///
+    /// This is used to key things like authentication tags, which shouldn't be possible to
+    /// make just by knowing some of the offsets. Users derive their own keys from it by hashing it.
+    #[inline]
+    fn key(&self) -> Option<[u8; 16]> {
+        None
+    }
+    /// Subtracts the next `buf.len()` offsets from the bytes of `buf`
+    ///
+    /// Used when decoding, where implementations with a simple pattern can do this a lot faster
+    /// than calling `next_offset` for each byte.
+    fn sub_offsets(&mut self, buf: &mut [u8]) {
+        for b in buf {
+            *b = b.wrapping_sub(self.next_offset());
+        }
+    }
+}
+
+impl<O: Offset + ?Sized> Offset for &mut O {
+    #[inline]
+    fn next_offset(&mut self) -> u8 {
+        (**self).next_offset()
+    }
+    #[inline]
+    fn step_back(&mut self) {
+        (**self).step_back()
+    }
+    #[inline]
+    fn reset(&mut self) {
+        (**self).reset()
+    }
+    #[inline]
+    fn skip(&mut self, n: u64) {
+        (**self).skip(n)
+    }
+    #[inline]
+    fn position(&self) -> Option<u64> {
+        (**self).position()
+    }
+    #[inline]
+    fn set_position(&mut self, pos: u64) {
+        (**self).set_position(pos)
+    }
+    #[inline]
+    fn key(&self) -> Option<[u8; 16]> {
+        (**self).key()
+    }
+    #[inline]
+    fn sub_offsets(&mut self, buf: &mut [u8]) {
+        (**self).sub_offsets(buf)
+    }
 }
 
 #[derive(Default, Debug, Clone, Copy)]
 /// An implementation of [`Offset`] using the sip hash of a string
 pub struct PassHashOffsetter {
     pass_hash: [u8; 8],
-    index: usize,
+    position: u64,
+    /// The half of the stretched hash the offsets aren't made from, if it was derived with a salt
+    secret: Option<u64>,
 }
 
+/// Number of rounds [`PassHashOffsetter::derive`] is recommended to be used with
+pub const DEFAULT_ROUNDS: u32 = 100_000;
+
+/// The most rounds a header can ask for
+///
+/// Headers aren't authenticated, so this keeps a crafted one from making decoding
+/// stretch the passphrase practically forever.
+pub const MAX_ROUNDS: u32 = 10 * DEFAULT_ROUNDS;
+
 impl PassHashOffsetter {
     /// Makes a new instance using the given string
     pub fn new(passphrase: &str) -> Self {
         let mut siphasher = SipHasher::new();
         siphasher.write(passphrase.as_bytes());
 
+        Self::from_hash(siphasher.finish())
+    }
+    /// Makes a new instance by stretching the passphrase with a salt
+    ///
+    /// The passphrase is hashed `rounds` times with SipHash keyed by the salt,
+    /// so that guessing passphrases is `rounds` times slower and the same passphrase
+    /// gives different offsets with different salts.
+    ///
+    /// Only instances made this way have a [`key`](Offset::key),
+    /// which is the other half of the stretched hash, so it can't be worked out from the offsets.
+    pub fn derive(passphrase: &str, salt: &[u8; 16], rounds: u32) -> Self {
+        let Hash128{h1, h2} = stretch(passphrase, salt, rounds);
+
+        Self {
+            secret: Some(h2),
+            ..Self::from_hash(h1)
+        }
+    }
+    #[inline]
+    fn from_hash(hash: u64) -> Self {
         let mut pass_hash = [0; 8];
-        LittleEndian::write_u64(&mut pass_hash, siphasher.finish());
+        LittleEndian::write_u64(&mut pass_hash, hash);
 
         Self {
             pass_hash,
-            index: 0,
+            position: 0,
+            secret: None,
         }
     }
 }
 
+/// Iterated 128-bit SipHash of `passphrase` keyed by `salt`
+fn stretch(passphrase: &str, salt: &[u8; 16], rounds: u32) -> Hash128 {
+    let k0 = LittleEndian::read_u64(&salt[..8]);
+    let k1 = LittleEndian::read_u64(&salt[8..]);
+
+    let mut hash = Hash128::default();
+    for round in 0..rounds.max(1) {
+        let mut siphasher = sip128::SipHasher::new_with_keys(k0, k1);
+        siphasher.write_u64(hash.h1);
+        siphasher.write_u64(hash.h2);
+        siphasher.write_u32(round);
+        siphasher.write(passphrase.as_bytes());
+        hash = siphasher.finish128();
+    }
+    hash
+}
+
 impl Offset for PassHashOffsetter {
     #[inline]
     fn next_offset(&mut self) -> u8 {
-        let ret = unsafe {*self.pass_hash.get_unchecked(self.index)};
-        self.index = (self.index + 1) & 7;
+        let ret = unsafe {*self.pass_hash.get_unchecked((self.position & 7) as usize)};
+        self.position = self.position.wrapping_add(1);
         ret
     }
     #[inline]
     fn step_back(&mut self) {
-        self.index = (self.index + 7) & 7;
+        self.position = self.position.wrapping_sub(1);
     }
     #[inline]
     fn reset(&mut self) {
-        self.index = 0;
+        self.position = 0;
+    }
+    #[inline]
+    fn skip(&mut self, n: u64) {
+        self.position = self.position.wrapping_add(n);
+    }
+    #[inline]
+    fn position(&self) -> Option<u64> {
+        Some(self.position)
+    }
+    #[inline]
+    fn set_position(&mut self, pos: u64) {
+        self.position = pos;
+    }
+    /// Only instances made with [`derive`](PassHashOffsetter::derive) have a key,
+    /// since the offsets of the others repeat the whole hash of the passphrase
+    #[inline]
+    fn key(&self) -> Option<[u8; 16]> {
+        let secret = self.secret?;
+        let mut key = [0; 16];
+        LittleEndian::write_u64(&mut key[..8], secret);
+        Some(key)
+    }
+    fn sub_offsets(&mut self, buf: &mut [u8]) {
+        let len = buf.len() as u64;
+        let mut pattern = self.pass_hash;
+        pattern.rotate_left((self.position & 7) as usize);
+
+        let mut chunks = buf.chunks_exact_mut(8);
+        for chunk in &mut chunks {
+            for (b, &o) in chunk.iter_mut().zip(&pattern) {
+                *b = b.wrapping_sub(o);
+            }
+        }
+        for (b, &o) in chunks.into_remainder().iter_mut().zip(&pattern) {
+            *b = b.wrapping_sub(o);
+        }
+
+        self.position = self.position.wrapping_add(len);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+/// An implementation of [`Offset`] that doesn't repeat
+///
+/// The offsets are SipHash in counter mode: block `n` of 16 offsets is the 128-bit SipHash
+/// of `n` keyed by a hash of the passphrase.
+pub struct KeystreamOffsetter {
+    key: (u64, u64),
+    position: u64,
+    block_index: u64,
+    block: [u8; 16],
+}
+
+impl KeystreamOffsetter {
+    /// Makes a new instance using the given string
+    pub fn new(passphrase: &str) -> Self {
+        let mut siphasher = sip128::SipHasher::new();
+        siphasher.write(passphrase.as_bytes());
+        let Hash128{h1, h2} = siphasher.finish128();
+
+        Self::with_key(h1, h2)
+    }
+    /// Makes a new instance by stretching the passphrase with a salt
+    ///
+    /// Uses the same stretching as [`PassHashOffsetter::derive`].
+    pub fn derive(passphrase: &str, salt: &[u8; 16], rounds: u32) -> Self {
+        let Hash128{h1, h2} = stretch(passphrase, salt, rounds);
+
+        Self::with_key(h1, h2)
+    }
+    /// Makes a new instance using the given key directly
+    pub fn with_key(k0: u64, k1: u64) -> Self {
+        Self {
+            key: (k0, k1),
+            position: 0,
+            block_index: 0,
+            block: Self::make_block((k0, k1), 0),
+        }
+    }
+    fn make_block((k0, k1): (u64, u64), index: u64) -> [u8; 16] {
+        let mut siphasher = sip128::SipHasher::new_with_keys(k0, k1);
+        siphasher.write_u64(index);
+        siphasher.finish128().as_bytes()
+    }
+    /// Makes sure `block` is the block of the current position
+    #[inline]
+    fn refill(&mut self) {
+        let index = self.position >> 4;
+        if index != self.block_index {
+            self.block = Self::make_block(self.key, index);
+            self.block_index = index;
+        }
+    }
+}
+
+impl Offset for KeystreamOffsetter {
+    #[inline]
+    fn next_offset(&mut self) -> u8 {
+        self.refill();
+        let ret = self.block[(self.position & 15) as usize];
+        self.position = self.position.wrapping_add(1);
+        ret
+    }
+    #[inline]
+    fn step_back(&mut self) {
+        self.position = self.position.wrapping_sub(1);
+    }
+    #[inline]
+    fn reset(&mut self) {
+        self.position = 0;
+    }
+    #[inline]
+    fn skip(&mut self, n: u64) {
+        self.position = self.position.wrapping_add(n);
+    }
+    #[inline]
+    fn position(&self) -> Option<u64> {
+        Some(self.position)
+    }
+    #[inline]
+    fn set_position(&mut self, pos: u64) {
+        self.position = pos;
+    }
+    #[inline]
+    fn key(&self) -> Option<[u8; 16]> {
+        let mut key = [0; 16];
+        LittleEndian::write_u64(&mut key[..8], self.key.0);
+        LittleEndian::write_u64(&mut key[8..], self.key.1);
+        Some(key)
+    }
+    fn sub_offsets(&mut self, mut buf: &mut [u8]) {
+        while !buf.is_empty() {
+            self.refill();
+            let start = (self.position & 15) as usize;
+            let n = (16 - start).min(buf.len());
+            let (head, rest) = std::mem::take(&mut buf).split_at_mut(n);
+
+            for (b, &o) in head.iter_mut().zip(&self.block[start..]) {
+                *b = b.wrapping_sub(o);
+            }
+            self.position = self.position.wrapping_add(n as u64);
+            buf = rest;
+        }
     }
 }
 
@@ -77,15 +342,49 @@ impl Offset for ZeroOffset {
     fn step_back(&mut self) { }
     #[inline]
     fn reset(&mut self) { }
+    #[inline]
+    fn skip(&mut self, _: u64) { }
+    #[inline]
+    fn set_position(&mut self, _: u64) { }
+    #[inline]
+    fn sub_offsets(&mut self, _: &mut [u8]) { }
+}
+
+/// Delta encodes `buf` in place
+///
+/// `last` is the byte that came before `buf` (0 at the start of a stream)
+/// and is updated to the last byte of `buf` before it was encoded,
+/// so that consecutive calls encode the same as if the buffers had been one.
+#[inline]
+pub fn encode_in_place<O: Offset>(buf: &mut [u8], offsetter: &mut O, last: &mut u8) {
+    for b in buf {
+        let plain = *b;
+        *b = plain.wrapping_add(offsetter.next_offset()).wrapping_add(*last);
+        *last = plain;
+    }
 }
 
-// TODO Implement `Seek` so that the `last` will be the right value and `offsetter` will have
-// the correct state
+/// Delta decodes `buf` in place
+///
+/// `last` is the decoded byte that came before `buf` (0 at the start of a stream)
+/// and is updated to the last decoded byte of `buf`,
+/// so that consecutive calls decode the same as if the buffers had been one.
+#[inline]
+pub fn decode_in_place<O: Offset>(buf: &mut [u8], offsetter: &mut O, last: &mut u8) {
+    offsetter.sub_offsets(buf);
+    simd::undelta(buf, last);
+}
 
 #[derive(Debug, Clone)]
 /// A `Write`r that writes each byte according to the delta encoding
+///
+/// With a [`Word`] wider than a byte, bytes are held back until they make up a whole word,
+/// so [`finish`](DeltaWriter::finish) has to be called to write the last ones.
 pub struct DeltaWriter<T: Write, O: Offset> {
-    last: u8,
+    delta: Delta,
+    /// Bytes that don't make up a whole word yet
+    tail: Vec<u8>,
+    pos: u64,
     inner: T,
     offsetter: O
 }
@@ -102,43 +401,126 @@ impl<T: Write, O: Offset> DeltaWriter<T, O> {
     /// Returns a [`DeltaWriter`] with a given [`Offset`]ter
     #[inline]
     pub fn with_offsetter(inner: T, offsetter: O) -> Self {
+        Self::with_delta(inner, offsetter, Delta::new())
+    }
+    /// Returns a [`DeltaWriter`] with a given [`Offset`]ter and [`Delta`] encoding
+    #[inline]
+    pub fn with_delta(inner: T, offsetter: O, delta: Delta) -> Self {
         Self {
             inner,
             offsetter,
-            last: 0,
+            delta,
+            tail: Vec::new(),
+            pos: 0,
         }
     }
+    /// Gets a reference to the inner `Write`r
+    #[inline]
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+    /// Gets a mutable reference to the inner `Write`r
+    ///
+    /// Bytes written directly to it aren't encoded and don't affect the encoding of later bytes
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
     /// Returns a the inner `Write`r
+    ///
+    /// Bytes that don't make up a whole word are lost, see [`finish`](DeltaWriter::finish)
     #[inline]
     pub fn into_inner(self) -> T {
         let Self{inner, ..} = self;
         inner
     }
+    /// Writes the bytes that don't make up a whole word, which ends the stream, and returns the inner `Write`r
+    pub fn finish(mut self) -> Result<T> {
+        let mut tail = std::mem::take(&mut self.tail);
+        self.delta.encode(&mut tail, &mut self.offsetter);
+        self.inner.write_all(&tail)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+/// Size of the scratch buffer bytes are encoded into before being written to the inner `Write`r
+const SCRATCH_SIZE: usize = 4096;
+
+impl<T: Write, O: Offset> DeltaWriter<T, O> {
+    /// Encodes `buf` into the start of `scratch`, which has to be at least as long as `buf`
+    #[inline]
+    fn encode_into(&mut self, buf: &[u8], scratch: &mut [u8]) {
+        let scratch = &mut scratch[..buf.len()];
+        scratch.copy_from_slice(buf);
+        self.delta.encode(scratch, &mut self.offsetter);
+    }
+    /// Encodes and writes the whole words of `tail` and `buf`, keeping the rest in `tail`
+    ///
+    /// The words are written with `write_all`, since a word that's only partly written can't be taken back.
+    fn write_words(&mut self, buf: &[u8]) -> Result<usize> {
+        let mut scratch = [0; SCRATCH_SIZE];
+        let held = self.tail.len();
+        let buf = &buf[..buf.len().min(SCRATCH_SIZE - held)];
+        scratch[..held].copy_from_slice(&self.tail);
+        scratch[held..held + buf.len()].copy_from_slice(buf);
+
+        let len = held + buf.len();
+        let whole = len - len % self.delta.word().size();
+        self.tail.clear();
+        self.tail.extend_from_slice(&scratch[whole..len]);
+
+        self.delta.encode(&mut scratch[..whole], &mut self.offsetter);
+        self.inner.write_all(&scratch[..whole])?;
+        self.pos += whole as u64;
+        Ok(buf.len())
+    }
 }
 
 impl<T: Write, O: Offset> Write for DeltaWriter<T, O> {
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        let mut total_len = 0;
-        for &b in buf {
-            let byte2write = b.wrapping_add(self.offsetter.next_offset()).wrapping_add(self.last);
-            match self.inner.write(&[byte2write])? {
-                0 => {
+        if self.delta.word() != Word::U8 {
+            return self.write_words(buf)
+        }
+        let mut scratch = [0; SCRATCH_SIZE];
+        let buf = &buf[..buf.len().min(SCRATCH_SIZE)];
+        let delta = self.delta;
+
+        self.encode_into(buf, &mut scratch);
+        let res = self.inner.write(&scratch[..buf.len()]);
+
+        // Roll the state back to match what the inner writer actually accepted
+        let n = *res.as_ref().unwrap_or(&0);
+        if n < buf.len() {
+            for _ in n..buf.len() {
+                self.offsetter.step_back();
+            }
+            self.delta = delta;
+            if n > 0 {
+                // Only the remembered bytes are wanted, so the offsets are taken back again
+                for _ in 0..n {
                     self.offsetter.step_back();
-                    return Ok(total_len)
-                },
-                n => total_len += n,
+                }
+                self.encode_into(&buf[..n], &mut scratch);
             }
-            self.last = b;
         }
-        debug_assert_eq!(total_len, buf.len());
-        Ok(total_len)
+        self.pos += n as u64;
+        res
     }
     #[inline]
-    fn write_all(&mut self, buf: &[u8]) -> Result<()> {
-        for &b in buf {
-            let byte2write = b.wrapping_add(self.offsetter.next_offset()).wrapping_add(self.last);
-            self.last = b;
-            self.inner.write_all(&[byte2write])?;
+    fn write_all(&mut self, mut buf: &[u8]) -> Result<()> {
+        if self.delta.word() != Word::U8 {
+            while !buf.is_empty() {
+                let n = self.write_words(buf)?;
+                buf = &buf[n..];
+            }
+            return Ok(())
+        }
+        let mut scratch = [0; SCRATCH_SIZE];
+        for chunk in buf.chunks(SCRATCH_SIZE) {
+            self.encode_into(chunk, &mut scratch);
+            self.inner.write_all(&scratch[..chunk.len()])?;
+            self.pos += chunk.len() as u64;
         }
         Ok(())
     }
@@ -150,10 +532,19 @@ impl<T: Write, O: Offset> Write for DeltaWriter<T, O> {
 
 #[derive(Debug, Clone)]
 /// A `Read`er that reads each byte according to the delta encoding
+///
+/// With a [`Word`] wider than a byte, bytes are held back until they make up a whole word
+/// or the end of the inner `Read`er has been reached.
 pub struct DeltaReader<T: Read, O: Offset> {
-    last: u8,
+    delta: Delta,
+    pos: u64,
     inner: T,
     offsetter: O,
+    /// Bytes decoded by `fill_buf` that haven't been consumed yet
+    buf: Vec<u8>,
+    buf_pos: usize,
+    /// Bytes that have been read but don't make up a whole word yet
+    tail: Vec<u8>,
 }
 
 impl<T: Read> DeltaReader<T, ZeroOffset> {
@@ -168,28 +559,225 @@ impl<T: Read, O: Offset> DeltaReader<T, O> {
     /// Returns a [`DeltaReader`] with a given [`Offset`]ter
     #[inline]
     pub fn with_offsetter(inner: T, offsetter: O) -> Self {
+        Self::with_delta(inner, offsetter, Delta::new())
+    }
+    /// Returns a [`DeltaReader`] with a given [`Offset`]ter and [`Delta`] encoding
+    #[inline]
+    pub fn with_delta(inner: T, offsetter: O, delta: Delta) -> Self {
         Self {
             inner,
             offsetter,
-            last: 0,
+            delta,
+            pos: 0,
+            buf: Vec::new(),
+            buf_pos: 0,
+            tail: Vec::new(),
         }
     }
+    /// Gets a reference to the inner `Read`er
+    #[inline]
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+    /// Gets a mutable reference to the inner `Read`er
+    ///
+    /// Bytes read directly from it aren't decoded and don't affect the decoding of later bytes
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
     /// Returns a the inner `Read`er
+    ///
+    /// Any bytes that have been buffered by [`BufRead::fill_buf`] and not consumed are lost
     #[inline]
     pub fn into_inner(self) -> T {
         let Self{inner, ..} = self;
         inner
     }
+    /// Forgets the bytes before, as at the start of a stream, while the offsets carry on
+    #[inline]
+    pub(crate) fn restart(&mut self) {
+        self.delta.reset();
+    }
+    /// Reads and decodes the next whole words into `buf`, or the bytes that are left at the end of the stream
+    fn read_words(&mut self) -> Result<()> {
+        let size = self.delta.word().size();
+        let mut scratch = [0; SCRATCH_SIZE];
+        loop {
+            let n = self.inner.read(&mut scratch)?;
+            self.tail.extend_from_slice(&scratch[..n]);
+            self.pos += n as u64;
+
+            let whole = if n == 0 { self.tail.len() } else { self.tail.len() - self.tail.len() % size };
+            if whole > 0 || n == 0 {
+                self.buf.clear();
+                self.buf.extend(self.tail.drain(..whole));
+                self.buf_pos = 0;
+                self.delta.decode(&mut self.buf, &mut self.offsetter);
+                return Ok(())
+            }
+        }
+    }
 }
 
 impl<T: Read, O: Offset> Read for DeltaReader<T, O> {
     #[inline]
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        let n = self.inner.read(buf)?;
-        for b in &mut buf[..n] {
-            self.last = b.wrapping_sub(self.offsetter.next_offset()).wrapping_sub(self.last);
-            *b = self.last;
+        if self.buf_pos >= self.buf.len() && self.delta.word() != Word::U8 && !buf.is_empty() {
+            self.read_words()?;
+        }
+        if self.buf_pos < self.buf.len() {
+            let n = (&self.buf[self.buf_pos..]).read(buf)?;
+            self.buf_pos += n;
+            return Ok(n)
+        }
+        if self.delta.word() != Word::U8 {
+            return Ok(0)
         }
+
+        let n = self.inner.read(buf)?;
+        self.delta.decode(&mut buf[..n], &mut self.offsetter);
+        self.pos += n as u64;
         Ok(n)
     }
 }
+
+/// Seeking needs the `Read`er to find the previous byte, so the inner `Write`r has to be readable
+///
+/// Note that overwriting bytes in the middle of the stream will break the decoding of the bytes
+/// after them, since each byte depends on the one before it.
+/// With a [`Word`] wider than a byte, only the start of a word can be seeked to.
+impl<T: Read + Write + Seek, O: Offset> Seek for DeltaWriter<T, O> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let target = target_position(&mut self.inner, self.pos, pos)?;
+        if target % self.delta.word().size() as u64 != 0 || !self.tail.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "can only seek between whole words"));
+        }
+        seek_delta(&mut self.inner, &mut self.offsetter, &mut self.delta, &mut self.pos, target)
+    }
+}
+
+impl<T: BufRead, O: Offset> BufRead for DeltaReader<T, O> {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        if self.buf_pos >= self.buf.len() {
+            if self.delta.word() != Word::U8 {
+                self.read_words()?;
+                return Ok(&self.buf[self.buf_pos..])
+            }
+            let data = self.inner.fill_buf()?;
+            self.buf.clear();
+            self.buf.extend_from_slice(data);
+            self.buf_pos = 0;
+
+            self.delta.decode(&mut self.buf, &mut self.offsetter);
+            let n = self.buf.len();
+            self.inner.consume(n);
+            self.pos += n as u64;
+        }
+        Ok(&self.buf[self.buf_pos..])
+    }
+    #[inline]
+    fn consume(&mut self, amt: usize) {
+        self.buf_pos = (self.buf_pos + amt).min(self.buf.len());
+    }
+}
+
+impl<T: Read + Seek, O: Offset> Seek for DeltaReader<T, O> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        // The state is at the start of the tail, so the tail is given back to be read again
+        if !self.tail.is_empty() {
+            self.inner.seek(SeekFrom::Current(-(self.tail.len() as i64)))?;
+            self.pos -= self.tail.len() as u64;
+            self.tail.clear();
+        }
+        // and at the end of the buffer, so the bytes in it that haven't been read have to be accounted for
+        let buffered = (self.buf.len() - self.buf_pos) as i64;
+        let pos = match pos {
+            SeekFrom::Current(n) => SeekFrom::Current(n - buffered),
+            pos => pos,
+        };
+        self.buf.clear();
+        self.buf_pos = 0;
+
+        // Only whole words can be decoded, so a target in the middle of a word is reached by decoding that word
+        let target = target_position(&mut self.inner, self.pos, pos)?;
+        let within = (target % self.delta.word().size() as u64) as usize;
+        seek_delta(&mut self.inner, &mut self.offsetter, &mut self.delta, &mut self.pos, target - within as u64)?;
+        if within > 0 {
+            self.read_words()?;
+            if within > self.buf.len() {
+                return Err(Error::new(ErrorKind::UnexpectedEof, "cannot seek past the end of a delta stream"));
+            }
+            self.buf_pos = within;
+        }
+        Ok(target)
+    }
+}
+
+/// Returns the position `to` refers to, relative to where the delta encoded stream started in `inner`
+///
+/// `pos` is the current position relative to that start.
+fn target_position<T: Seek>(inner: &mut T, pos: u64, to: SeekFrom) -> Result<u64> {
+    let target = match to {
+        SeekFrom::Start(n) => Some(n),
+        SeekFrom::Current(n) => offset_position(pos, n),
+        SeekFrom::End(n) => {
+            let start = inner.stream_position()? - pos;
+            let end = inner.seek(SeekFrom::End(0))? - start;
+            inner.seek(SeekFrom::Start(start + pos))?;
+            offset_position(end, n)
+        }
+    };
+    target.ok_or_else(|| Error::new(ErrorKind::InvalidInput, "invalid seek to a negative or overflowing position"))
+}
+
+/// Moves `inner` to the position `target`, which has to be the start of a word, and updates `delta` and `offsetter` to match it
+///
+/// Since the bytes remembered by `delta` depend on every byte before it, it has to be recalculated
+/// by decoding the stream from the beginning when seeking backwards,
+/// and decoding the skipped bytes when seeking forwards.
+/// `pos` is the current position relative to where the delta encoded stream started in `inner`.
+fn seek_delta<T: Read + Seek, O: Offset>(inner: &mut T, offsetter: &mut O, delta: &mut Delta, pos: &mut u64, target: u64) -> Result<u64> {
+    let start = inner.stream_position()? - *pos;
+
+    if target < *pos {
+        inner.seek(SeekFrom::Start(start))?;
+        offsetter.reset();
+        delta.reset();
+        *pos = 0;
+    }
+
+    let size = delta.word().size();
+    let mut buf = [0; 4096];
+    // Bytes of a word that has only been read in part
+    let mut held = 0;
+    while *pos < target {
+        let len = buf.len().min((target - *pos) as usize);
+        held += match inner.read(&mut buf[held..len]) {
+            // Nothing being read only means the end of the stream if there was room to read into
+            Ok(0) if held < len => Err(Error::new(ErrorKind::UnexpectedEof, "cannot seek past the end of a delta stream")),
+            res => res,
+        }.or_else(|e| {
+            // The part of a word that was read is given back
+            inner.seek(SeekFrom::Current(-(held as i64)))?;
+            Err(e)
+        })?;
+
+        let whole = held - held % size;
+        delta.decode(&mut buf[..whole], offsetter);
+        *pos += whole as u64;
+        buf.copy_within(whole..held, 0);
+        held -= whole;
+    }
+
+    Ok(*pos)
+}
+
+#[inline]
+fn offset_position(pos: u64, offset: i64) -> Option<u64> {
+    if offset >= 0 {
+        pos.checked_add(offset as u64)
+    } else {
+        pos.checked_sub(offset.wrapping_neg() as u64)
+    }
+}
@@ -1,10 +1,12 @@
 #![warn(clippy::all)]
 
-use delta_l::{PassHashOffsetter, encode_no_checksum, encode_with_checksum, decode};
-use delta_l::header::Error::{Io, InvalidHeader, ChecksumMismatch};
+use delta_l::{Delta, Combine, PassHashOffsetter, DEFAULT_ROUNDS};
+use delta_l::header::{Error, Kdf, Metadata, Options, ArmorWriter, read_header, encode_v2, encode_v2_with_passphrase, encode_v2_streaming, encode_v2_streaming_with_passphrase, encode_parallel, encode_parallel_with_passphrase, decode_parallel, decode_parallel_with_passphrase};
+use delta_l::header::Error::{Io, InvalidHeader, ChecksumMismatch, PassphraseRequired, Unsupported, AuthenticationFailed, WrongPassphrase, Truncated, TrailingData};
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::fs::File;
+use std::io::{self, Write, Seek, SeekFrom};
 use std::io::ErrorKind::NotFound;
 
 use clap::{App, Arg};
@@ -25,8 +27,56 @@ impl Mode{
 
 use crate::Mode::*;
 
+/// The size of the chunks when encrypting with `--threads`
+const CHUNK_SIZE: u32 = 1 << 20;
+
+/// The most threads `--threads` uses, as each of them holds a chunk in memory
+const MAX_THREADS: usize = 256;
+
+/// A file that isn't created until it's written to
+///
+/// Decryption checks the file before writing anything,
+/// so this makes sure an existing file isn't overwritten when the check fails.
+struct LazyFile{
+    path: PathBuf,
+    file: Option<File>,
+}
+
+impl LazyFile{
+    fn new(path: PathBuf) -> Self{
+        LazyFile{path, file: None}
+    }
+    fn get(&mut self) -> io::Result<&mut File>{
+        if self.file.is_none(){
+            self.file = Some(File::create(&self.path)?);
+        }
+        Ok(self.file.as_mut().unwrap())
+    }
+}
+
+/// Encodes into `result_file` with ASCII armor around it
+fn armored<F>(result_file: &mut LazyFile, encode: F) -> Result<(), Error>
+    where F: FnOnce(&mut ArmorWriter<&mut File>) -> Result<(), Error> {
+    let mut armor = ArmorWriter::new(result_file.get()?);
+    encode(&mut armor)?;
+    armor.finish()?;
+    Ok(())
+}
+
+impl Write for LazyFile{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize>{
+        self.get()?.write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()>{
+        match self.file{
+            Some(ref mut f) => f.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
 fn main() {
-    let matches = App::new(env!("CARGO_PKG_NAME"))
+    let app = App::new(env!("CARGO_PKG_NAME"))
         .version(env!("CARGO_PKG_VERSION"))
         .author(env!("CARGO_PKG_AUTHORS"))
         .about(env!("CARGO_PKG_DESCRIPTION"))
@@ -54,7 +104,29 @@ fn main() {
             .long("checksum")
             .help("Disables checksum feature when encrypting: - This is read from the header when decrypting"),
         )
-        .get_matches();
+        .arg(Arg::with_name("threads")
+            .short("t")
+            .long("threads")
+            .takes_value(true)
+            .help("Encrypts in chunks with their own checksums on this many threads, instead of with a single checksum and authentication tag. Chunked files are also decrypted on this many threads"),
+        )
+        .arg(Arg::with_name("armor")
+            .short("a")
+            .long("armor")
+            .help("Encrypts into text with ASCII armor, which can be pasted into emails and such. Armor is detected when decrypting"),
+        )
+        .arg(Arg::with_name("xor")
+            .short("x")
+            .long("xor")
+            .help("Chains the bytes with XOR instead of addition when encrypting: - This is read from the header when decrypting"),
+        );
+    #[cfg(feature = "compress")]
+    let app = app.arg(Arg::with_name("compress")
+        .short("z")
+        .long("compress")
+        .help("Compresses the file before encrypting it, unless it's encrypted in chunks"),
+    );
+    let matches = app.get_matches();
 
     let file_path = matches.value_of("FILE").unwrap();
 
@@ -68,15 +140,55 @@ fn main() {
     let passphrase = matches.value_of("passphrase");
     let checksum = !matches.is_present("checksum");
     let force_overwite = matches.is_present("yes");
+    let armor = matches.is_present("armor");
+    let threads = match matches.value_of("threads").map(str::parse::<usize>) {
+        Some(Ok(threads)) if threads > 0 => Some(threads.min(MAX_THREADS)),
+        Some(_) => return println!("The number of threads has to be a positive whole number."),
+        None => None,
+    };
 
-    let passhash = if let Some(ref pp) = passphrase{
-        PassHashOffsetter::new(pp)
-    }else{Default::default()};
+    let mut f = match File::open(file_path){
+        Ok(f) => f,
+        Err(e) => match e.kind(){
+            NotFound => return println!("Couldn't find the specified file.\nPlease make sure the file exists."),
+            _        => return println!("An unknown error occured, opening the file:\n{:?}", e)
+        }
+    };
+
+    // The metadata of the original file, which is read from the header when decrypting,
+    // and whether the header is covered by an authentication tag
+    let (metadata, authenticated) = match mode {
+        Encrypt => (Metadata::from_file(file_path).ok(), false),
+        Decrypt => {
+            // Errors in the header are reported when decrypting
+            let info = read_header(&mut f).ok();
+            if let Err(e) = f.seek(SeekFrom::Start(0)) {
+                return println!("An unknown error occured, reading the file:\n{:?}", e.kind())
+            }
+            match info {
+                Some(info) => (Some(info.metadata), info.authenticated),
+                None => (None, false),
+            }
+        }
+    };
+
+    // Only the name is used, so the header can't point to another directory.
+    // Anyone could have written a header that isn't authenticated, so it can't pick which file is written to.
+    // The tag is checked before anything is written, and a wrong one stops the decryption.
+    let original_path = match mode {
+        Decrypt if authenticated && passphrase.is_some() => metadata.as_ref()
+            .and_then(|metadata| metadata.name.as_ref())
+            .and_then(|name| Path::new(name).file_name())
+            .map(|name| Path::new(file_path).with_file_name(name))
+            .filter(|path| path != Path::new(file_path)),
+        _ => None,
+    };
 
-    let to: PathBuf = to_file.map(|s| s.to_owned())
-        .unwrap_or(file_path.to_owned() + mode.get_mode_standard_extension())
-        // From `String` into `PathBuf`
-        .into();
+    let to: PathBuf = match (to_file, original_path) {
+        (Some(to_file), _) => to_file.into(),
+        (None, Some(original_path)) => original_path,
+        (None, None) => (file_path.to_owned() + mode.get_mode_standard_extension()).into(),
+    };
 
     if to.exists() && !force_overwite{
         println!("Output file already exists; do you want to overwrite (yes/no)?");
@@ -95,34 +207,80 @@ fn main() {
         }
     }
 
-    let mut f = match File::open(file_path){
-        Ok(f) => f,
-        Err(e) => match e.kind(){
-            NotFound => return println!("Couldn't find the specified file.\nPlease make sure the file exists."),
-            _        => return println!("An unknown error occured, opening the file:\n{:?}", e)
-        }
-    };
+    let mut result_file = LazyFile::new(to.clone());
+
+    let passhash = PassHashOffsetter::default();
+    // With a passphrase the authentication tag replaces the checksum, which anyone could check a guessed plaintext against
+    let mut options = Options::new().checksum(checksum && passphrase.is_none()).authenticate(passphrase.is_some()).length(true).key_check(true);
+    let kdf = Kdf::Keystream{rounds: DEFAULT_ROUNDS};
 
-    let mut result_file = File::create(&to).unwrap();
+    // Chunks have their own checksums and can't be authenticated
+    let mut chunked_options = Options::new().key_check(true);
 
-    let res = match (mode, checksum){
-        (Encrypt, true) => encode_with_checksum(passhash, &mut f, &mut result_file).map_err(From::from),
-        (Encrypt, false) => encode_no_checksum(passhash, &mut f, &mut result_file).map_err(From::from),
-        (Decrypt, true) => decode(passhash, &mut f, &mut result_file),
-        (Decrypt, false) => {
+    if let (Encrypt, Some(metadata)) = (mode, &metadata) {
+        options = options.metadata(metadata.clone());
+        chunked_options = chunked_options.metadata(metadata.clone());
+    }
+    if matches.is_present("xor") {
+        let delta = Delta::new().with_combine(Combine::Xor);
+        options = options.delta(delta);
+        chunked_options = chunked_options.delta(delta);
+    }
+    #[cfg(feature = "compress")]
+    {
+        options = options.compress(matches.is_present("compress"));
+    }
+
+    let res = match (mode, checksum, passphrase){
+        // The armor can't be seeked, so the checksum goes in a trailer
+        (Encrypt, _, Some(pp)) if armor => armored(&mut result_file, |rf| match threads {
+            Some(threads) => encode_parallel_with_passphrase(pp, kdf, CHUNK_SIZE, threads, &chunked_options, &mut f, rf),
+            None => encode_v2_streaming_with_passphrase(pp, kdf, &options, &mut f, rf),
+        }),
+        (Encrypt, _, None) if armor => armored(&mut result_file, |rf| match threads {
+            Some(threads) => encode_parallel(passhash, CHUNK_SIZE, threads, &chunked_options, &mut f, rf),
+            None => encode_v2_streaming(passhash, &options, &mut f, rf),
+        }),
+        (Encrypt, _, Some(pp)) => result_file.get().map_err(From::from)
+            .and_then(|rf| match threads {
+                Some(threads) => encode_parallel_with_passphrase(pp, kdf, CHUNK_SIZE, threads, &chunked_options, &mut f, rf),
+                None => encode_v2_with_passphrase(pp, kdf, &options, &mut f, rf),
+            }),
+        (Encrypt, _, None) => result_file.get().map_err(From::from)
+            .and_then(|rf| match threads {
+                Some(threads) => encode_parallel(passhash, CHUNK_SIZE, threads, &chunked_options, &mut f, rf),
+                None => encode_v2(passhash, &options, &mut f, rf),
+            }),
+        (Decrypt, true, Some(pp)) => decode_parallel_with_passphrase(pp, threads.unwrap_or(1), &mut f, &mut result_file),
+        (Decrypt, true, None) => decode_parallel(passhash, threads.unwrap_or(1), &mut f, &mut result_file),
+        (Decrypt, false, _) => {
             eprintln!("Checksum flag is only available when encrypting.\n");
             return
         }
     };
 
+    // An empty result is never written to
+    let res = res.and_then(|()| result_file.get().map(|_| ()).map_err(From::from));
+
     match res {
         Ok(()) => {
+            if let (Decrypt, Some(metadata)) = (mode, &metadata) {
+                if let Err(e) = result_file.get().and_then(|rf| metadata.apply(rf)) {
+                    println!("Couldn't restore the modification time and permissions:\n{:?}", e.kind());
+                }
+            }
             println!("Result file has been saved to {}", to.to_str().unwrap_or("<nil>"))
         },
         Err(e) => match e {
             Io(e)         => println!("An unknown error occured, encrypting the file:\n{:?}", e.kind()),
             InvalidHeader => println!("Invalid header error:\nThe specified file wasn't a valid .delta file."),
-            ChecksumMismatch => println!("Checksum mismatch detetected!\nPassphrase is probably incorrect."),
+            ChecksumMismatch => println!("Checksum mismatch detetected!\nThe decrypted file doesn't match the checksum in the header."),
+            PassphraseRequired => println!("The file was encrypted with a passphrase.\nPlease specify it using --pass."),
+            Unsupported => println!("Unsupported header error:\nThe file was made by a newer version of this program."),
+            WrongPassphrase => println!("Wrong passphrase!\nThe passphrase doesn't match the one the file was encrypted with."),
+            AuthenticationFailed => println!("Authentication failed!\nThe passphrase is incorrect or the file has been tampered with."),
+            Truncated{expected, actual} => println!("The file is truncated!\nOnly {} of {} bytes are there.", actual, expected),
+            TrailingData => println!("The file has trailing data!\nIt is longer than the length in the header."),
         },
     }
 }
@@ -0,0 +1,105 @@
+//! Vectorised undoing of the delta step
+//!
+//! Decoding `last = b - last` is a running alternating sum. Negating every other byte turns it
+//! into a plain prefix sum, which can be computed for a whole vector in `log2(lanes)` shifted adds.
+//! With `e[i] = (-1)^i * b[i]` and `q[i] = e[i] + q[i-1]`, where `q[-1] = -last`,
+//! the decoded byte is `(-1)^i * q[i]`.
+//! Vectors always have an even number of lanes, so the signs line up from one vector to the next.
+
+/// Decodes `buf` in place, after the offsets have been subtracted from it
+#[inline]
+pub(crate) fn undelta(buf: &mut [u8], last: &mut u8) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if buf.len() >= 32 && is_x86_feature_detected!("avx2") {
+            return unsafe { x86_64::undelta_avx2(buf, last) }
+        } else if buf.len() >= 16 {
+            return unsafe { x86_64::undelta_sse2(buf, last) }
+        }
+    }
+
+    undelta_scalar(buf, last)
+}
+
+#[inline]
+fn undelta_scalar(buf: &mut [u8], last: &mut u8) {
+    for b in buf {
+        *last = b.wrapping_sub(*last);
+        *b = *last;
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86_64 {
+    use std::arch::x86_64::*;
+
+    use super::undelta_scalar;
+
+    /// SSE2 is part of the x86_64 baseline, so this is always safe to call
+    pub(super) unsafe fn undelta_sse2(buf: &mut [u8], last: &mut u8) {
+        let mask = _mm_set1_epi16(0xFF00u16 as i16);
+        let mut carry = _mm_set1_epi8(last.wrapping_neg() as i8);
+
+        let mut chunks = buf.chunks_exact_mut(16);
+        for chunk in &mut chunks {
+            let ptr = chunk.as_mut_ptr() as *mut __m128i;
+            let mut x = _mm_loadu_si128(ptr);
+            // Negate the odd bytes
+            x = _mm_sub_epi8(_mm_xor_si128(x, mask), mask);
+
+            x = _mm_add_epi8(x, _mm_slli_si128(x, 1));
+            x = _mm_add_epi8(x, _mm_slli_si128(x, 2));
+            x = _mm_add_epi8(x, _mm_slli_si128(x, 4));
+            x = _mm_add_epi8(x, _mm_slli_si128(x, 8));
+            x = _mm_add_epi8(x, carry);
+
+            // Broadcast the last byte
+            carry = _mm_unpackhi_epi8(x, x);
+            carry = _mm_shufflehi_epi16(carry, 0xFF);
+            carry = _mm_unpackhi_epi64(carry, carry);
+
+            _mm_storeu_si128(ptr, _mm_sub_epi8(_mm_xor_si128(x, mask), mask));
+        }
+
+        let rest = chunks.into_remainder();
+        *last = (_mm_cvtsi128_si32(carry) as u8).wrapping_neg();
+        undelta_scalar(rest, last);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn undelta_avx2(buf: &mut [u8], last: &mut u8) {
+        let mask = _mm256_set1_epi16(0xFF00u16 as i16);
+        let fifteen = _mm256_set1_epi8(15);
+        let mut carry = _mm256_set1_epi8(last.wrapping_neg() as i8);
+
+        let mut chunks = buf.chunks_exact_mut(32);
+        for chunk in &mut chunks {
+            let ptr = chunk.as_mut_ptr() as *mut __m256i;
+            let mut x = _mm256_loadu_si256(ptr);
+            x = _mm256_sub_epi8(_mm256_xor_si256(x, mask), mask);
+
+            // These shifts stay within each 128-bit lane
+            x = _mm256_add_epi8(x, _mm256_slli_si256(x, 1));
+            x = _mm256_add_epi8(x, _mm256_slli_si256(x, 2));
+            x = _mm256_add_epi8(x, _mm256_slli_si256(x, 4));
+            x = _mm256_add_epi8(x, _mm256_slli_si256(x, 8));
+            // so the sum of the low lane has to be carried into the high lane
+            let lane_ends = _mm256_shuffle_epi8(x, fifteen);
+            x = _mm256_add_epi8(x, _mm256_permute2x128_si256(lane_ends, lane_ends, 0x08));
+            x = _mm256_add_epi8(x, carry);
+
+            let lane_ends = _mm256_shuffle_epi8(x, fifteen);
+            carry = _mm256_permute2x128_si256(lane_ends, lane_ends, 0x11);
+
+            _mm256_storeu_si256(ptr, _mm256_sub_epi8(_mm256_xor_si256(x, mask), mask));
+        }
+
+        let rest = chunks.into_remainder();
+        *last = (_mm256_cvtsi256_si32(carry) as u8).wrapping_neg();
+        if rest.len() >= 16 {
+            undelta_sse2(rest, last);
+        } else {
+            undelta_scalar(rest, last);
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use std::io::{Cursor, Read, Write};
+
+use delta_l::KeystreamOffsetter;
+use delta_l::header::{self, Error, Kdf, Options, ArmorWriter, ArmorReader};
+
+const TEST_DATA: &[u8] = include_bytes!("data/test_data.bin");
+
+fn armor(data: &[u8]) -> String {
+    let mut armor = ArmorWriter::new(Vec::new());
+    armor.write_all(data).unwrap();
+    String::from_utf8(armor.finish().unwrap()).unwrap()
+}
+
+fn dearmor(text: &str) -> std::io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    ArmorReader::new(text.as_bytes()).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+#[test]
+fn base64(){
+    let vectors: &[(&[u8], &str)] = &[
+        (b"", ""),
+        (b"f", "Zg=="),
+        (b"fo", "Zm8="),
+        (b"foo", "Zm9v"),
+        (b"foob", "Zm9vYg=="),
+        (b"fooba", "Zm9vYmE="),
+        (b"foobar", "Zm9vYmFy"),
+    ];
+    for &(data, base64) in vectors {
+        let expected = if base64.is_empty() {
+            "-----BEGIN DELTA-L-----\n-----END DELTA-L-----\n".to_owned()
+        } else {
+            format!("-----BEGIN DELTA-L-----\n{}\n-----END DELTA-L-----\n", base64)
+        };
+        assert_eq!(armor(data), expected);
+        assert_eq!(dearmor(&expected).unwrap(), data);
+    }
+}
+
+#[test]
+fn round_trip(){
+    let text = armor(TEST_DATA);
+    assert!(text.lines().all(|line| line.len() <= 64 || line.starts_with("-----")));
+    assert_eq!(dearmor(&text).unwrap(), TEST_DATA);
+
+    // Writing a byte at a time gives the same armor
+    let mut armor = ArmorWriter::new(Vec::new());
+    for &byte in TEST_DATA {
+        armor.write_all(&[byte]).unwrap();
+    }
+    assert_eq!(String::from_utf8(armor.finish().unwrap()).unwrap(), text);
+
+    // Other line endings and wrapping are accepted
+    let rewrapped = text.replace('\n', "\r\n").replace("AB", "A\r\n  B");
+    assert_eq!(dearmor(&rewrapped).unwrap(), TEST_DATA);
+}
+
+#[test]
+fn invalid_armor(){
+    for text in &[
+        "-----BEGIN DELTA-L-----\nZm9vYmFy\n",
+        "-----BEGIN DELTA-X-----\nZm9vYmFy\n-----END DELTA-L-----\n",
+        "-----BEGIN DELTA-L-----\nZm9vYmF\n-----END DELTA-L-----\n",
+        "-----BEGIN DELTA-L-----\nZm9v*mFy\n-----END DELTA-L-----\n",
+        "-----BEGIN DELTA-L-----\nZg==Zm9v\n-----END DELTA-L-----\n",
+    ] {
+        assert!(dearmor(text).is_err(), "{:?} was accepted", text);
+    }
+}
+
+#[test]
+fn decode_detects_armor(){
+    let mut encrypted = ArmorWriter::new(Vec::new());
+    header::encode_v2_streaming(KeystreamOffsetter::new("armor"), &Options::new().authenticate(true), &mut &*TEST_DATA, &mut encrypted).unwrap();
+    let encrypted = encrypted.finish().unwrap();
+    assert!(encrypted.starts_with(b"-----BEGIN DELTA-L-----\n"));
+
+    let mut dec = Vec::new();
+    delta_l::decode(KeystreamOffsetter::new("armor"), &mut &*encrypted, &mut dec).unwrap();
+    assert_eq!(dec, TEST_DATA);
+
+    let mut dec = Vec::new();
+    delta_l::decode_verified(KeystreamOffsetter::new("armor"), &mut Cursor::new(&encrypted), &mut dec).unwrap();
+    assert_eq!(dec, TEST_DATA);
+
+    let mut dec = Vec::new();
+    header::decode_parallel(KeystreamOffsetter::new("armor"), 2, &mut Cursor::new(&encrypted), &mut dec).unwrap();
+    assert_eq!(dec, TEST_DATA);
+
+    assert!(header::read_header(&mut &*encrypted).unwrap().authenticated);
+
+    match delta_l::decode(KeystreamOffsetter::new("wrong"), &mut &*encrypted, &mut Vec::new()) {
+        Err(Error::AuthenticationFailed) => (),
+        r => panic!("expected AuthenticationFailed, got {:?}", r),
+    }
+}
+
+#[test]
+fn decode_with_passphrase_detects_armor(){
+    let mut encrypted = ArmorWriter::new(Vec::new());
+    header::encode_chunked_with_passphrase("armor", Kdf::Keystream{rounds: 10}, 1000, &Options::new(), &mut &*TEST_DATA, &mut encrypted).unwrap();
+    let encrypted = encrypted.finish().unwrap();
+
+    let mut dec = Vec::new();
+    header::decode_with_passphrase("armor", &mut &*encrypted, &mut dec).unwrap();
+    assert_eq!(dec, TEST_DATA);
+
+    let mut dec = Vec::new();
+    header::decode_verified_with_passphrase("armor", &mut Cursor::new(&encrypted), &mut dec).unwrap();
+    assert_eq!(dec, TEST_DATA);
+
+    let mut dec = Vec::new();
+    header::decode_parallel_with_passphrase("armor", 3, &mut Cursor::new(&encrypted), &mut dec).unwrap();
+    assert_eq!(dec, TEST_DATA);
+}
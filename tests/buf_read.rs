@@ -0,0 +1,40 @@
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write, Cursor};
+
+use delta_l::{DeltaReader, DeltaWriter, PassHashOffsetter};
+
+const TEXT: &str = "first line\nsecond line\n\nfourth line after an empty one\nlast line without newline";
+
+fn encrypted() -> Vec<u8> {
+    let mut writer = DeltaWriter::with_offsetter(Vec::new(), PassHashOffsetter::new("lines"));
+    writer.write_all(TEXT.as_bytes()).unwrap();
+    writer.into_inner()
+}
+
+#[test]
+fn lines(){
+    let data = encrypted();
+    // A small capacity makes lines span several buffers
+    let reader = DeltaReader::with_offsetter(BufReader::with_capacity(7, &*data), PassHashOffsetter::new("lines"));
+
+    let lines: Vec<String> = reader.lines().map(|l| l.unwrap()).collect();
+    assert_eq!(lines, TEXT.lines().collect::<Vec<_>>());
+}
+
+#[test]
+fn mixed_reads_and_seeks(){
+    let data = encrypted();
+    let mut reader = DeltaReader::with_offsetter(BufReader::with_capacity(16, Cursor::new(data)), PassHashOffsetter::new("lines"));
+
+    let mut line = Vec::new();
+    reader.read_until(b'\n', &mut line).unwrap();
+    assert_eq!(line, b"first line\n");
+
+    let mut buf = [0; 6];
+    reader.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"second");
+
+    assert_eq!(reader.seek(SeekFrom::Current(-3)).unwrap(), 14);
+    let mut rest = String::new();
+    reader.read_to_string(&mut rest).unwrap();
+    assert_eq!(rest, &TEXT[14..]);
+}
@@ -0,0 +1,161 @@
+use std::io::{Cursor, Read};
+
+use delta_l::{PassHashOffsetter, KeystreamOffsetter, ZeroOffset};
+use delta_l::header::{self, ChunkedReader, Decoder, Error, Kdf, Options};
+
+const TEST_DATA: &[u8] = include_bytes!("data/test_data.bin");
+const CHUNK_SIZE: u32 = 1000;
+
+fn encode_chunked() -> Vec<u8> {
+    let mut encrypted = Vec::new();
+    header::encode_chunked(KeystreamOffsetter::new("chunked"), CHUNK_SIZE, &Options::new(), &mut &*TEST_DATA, &mut encrypted).unwrap();
+    encrypted
+}
+
+#[test]
+fn round_trip(){
+    let encrypted = encode_chunked();
+
+    let mut dec = Vec::new();
+    delta_l::decode(KeystreamOffsetter::new("chunked"), &mut &*encrypted, &mut dec).unwrap();
+    assert_eq!(dec, TEST_DATA);
+
+    let mut dec = Vec::new();
+    delta_l::decode_verified(KeystreamOffsetter::new("chunked"), &mut Cursor::new(&encrypted), &mut dec).unwrap();
+    assert_eq!(dec, TEST_DATA);
+
+    let mut dec = Vec::new();
+    Decoder::new(&*encrypted, KeystreamOffsetter::new("chunked")).read_to_end(&mut dec).unwrap();
+    assert_eq!(dec, TEST_DATA);
+
+    let info = header::read_header(&mut &*encrypted).unwrap();
+    assert_eq!(info.chunk_size, Some(CHUNK_SIZE));
+}
+
+#[test]
+fn passphrase_round_trip(){
+    let mut encrypted = Vec::new();
+    header::encode_chunked_with_passphrase("chunked", Kdf::PassHash{rounds: 10}, CHUNK_SIZE, &Options::new(), &mut &*TEST_DATA, &mut encrypted).unwrap();
+
+    let mut dec = Vec::new();
+    delta_l::decode_with_passphrase("chunked", &mut &*encrypted, &mut dec).unwrap();
+    assert_eq!(dec, TEST_DATA);
+
+    let mut reader = ChunkedReader::with_passphrase(Cursor::new(&encrypted), "chunked").unwrap();
+    let mut chunk = Vec::new();
+    reader.read_chunk(1, &mut chunk).unwrap();
+    assert_eq!(chunk, &TEST_DATA[1000..2000]);
+}
+
+#[test]
+fn random_access(){
+    let encrypted = encode_chunked();
+    let mut reader = ChunkedReader::new(Cursor::new(&encrypted), KeystreamOffsetter::new("chunked")).unwrap();
+
+    let count = TEST_DATA.len().div_ceil(CHUNK_SIZE as usize);
+    assert_eq!(reader.chunk_count(), count);
+    assert_eq!(reader.chunk_size(), CHUNK_SIZE);
+
+    let mut chunk = Vec::new();
+    for (i, expected) in TEST_DATA.chunks(CHUNK_SIZE as usize).enumerate().rev() {
+        reader.read_chunk(i, &mut chunk).unwrap();
+        assert_eq!(chunk, expected);
+    }
+}
+
+#[test]
+fn damage_is_contained(){
+    let mut encrypted = encode_chunked();
+    let header_len = header::read_header(&mut &*encrypted).unwrap().header_len as usize;
+    // In the ciphertext of the second chunk
+    encrypted[header_len + 12 + 1000 + 12 + 500] ^= 1;
+
+    let mut reader = ChunkedReader::new(Cursor::new(&encrypted), KeystreamOffsetter::new("chunked")).unwrap();
+    let mut chunk = Vec::new();
+    for i in 0..reader.chunk_count() {
+        match reader.read_chunk(i, &mut chunk) {
+            Err(Error::ChecksumMismatch) if i == 1 => (),
+            Ok(()) if i != 1 => assert_eq!(chunk, TEST_DATA.chunks(CHUNK_SIZE as usize).nth(i).unwrap()),
+            r => panic!("unexpected result for chunk {}: {:?}", i, r),
+        }
+    }
+
+    match delta_l::decode_verified(KeystreamOffsetter::new("chunked"), &mut Cursor::new(&encrypted), &mut Vec::new()) {
+        Err(Error::ChecksumMismatch) => (),
+        r => panic!("expected ChecksumMismatch, got {:?}", r),
+    }
+}
+
+#[test]
+fn not_chunked(){
+    let mut encrypted = Cursor::new(Vec::new());
+    header::encode_v2(PassHashOffsetter::new("chunked"), &Options::new(), &mut &*TEST_DATA, &mut encrypted).unwrap();
+    encrypted.set_position(0);
+
+    match ChunkedReader::new(encrypted, PassHashOffsetter::new("chunked")) {
+        Err(Error::InvalidHeader) => (),
+        r => panic!("expected InvalidHeader, got {:?}", r.map(|_| ())),
+    }
+}
+
+#[test]
+fn empty(){
+    let mut encrypted = Vec::new();
+    header::encode_chunked(ZeroOffset, CHUNK_SIZE, &Options::new(), &mut &b""[..], &mut encrypted).unwrap();
+
+    let mut dec = Vec::new();
+    delta_l::decode(ZeroOffset, &mut &*encrypted, &mut dec).unwrap();
+    assert!(dec.is_empty());
+    assert_eq!(ChunkedReader::new(Cursor::new(&encrypted), ZeroOffset).unwrap().chunk_count(), 0);
+}
+
+#[test]
+fn unsupported_options(){
+    for options in &[Options::new().authenticate(true), Options::new().length(true)] {
+        let mut encrypted = Vec::new();
+        match header::encode_chunked(KeystreamOffsetter::new("chunked"), CHUNK_SIZE, options, &mut &*TEST_DATA, &mut encrypted) {
+            Err(Error::Unsupported) => (),
+            r => panic!("expected Unsupported, got {:?}", r),
+        }
+        assert!(encrypted.is_empty());
+    }
+}
+
+#[test]
+fn only_last_chunk_short(){
+    // A short first chunk would move the offsets of all the chunks after it
+    let mut short = Vec::new();
+    header::encode_chunked(KeystreamOffsetter::new("chunked"), CHUNK_SIZE, &Options::new(), &mut &TEST_DATA[..500], &mut short).unwrap();
+    let header_len = header::read_header(&mut &*short).unwrap().header_len as usize;
+    let record = short[header_len..header_len + 12 + 500].to_vec();
+    short.splice(header_len..header_len, record);
+
+    let mut dec = Vec::new();
+    let err = Decoder::new(&*short, KeystreamOffsetter::new("chunked")).read_to_end(&mut dec).unwrap_err();
+    match err.into_inner().and_then(|e| e.downcast::<Error>().ok()).map(|e| *e) {
+        Some(Error::InvalidHeader) => (),
+        r => panic!("expected InvalidHeader, got {:?}", r),
+    }
+    match header::decode_parallel(KeystreamOffsetter::new("chunked"), 2, &mut Cursor::new(&short), &mut Vec::new()) {
+        Err(Error::InvalidHeader) => (),
+        r => panic!("expected InvalidHeader, got {:?}", r),
+    }
+
+    // The length of the first record and the offset of the second in the index
+    let mut encrypted = encode_chunked();
+    let header_len = header::read_header(&mut &*encrypted).unwrap().header_len as usize;
+    encrypted[header_len] = 0xE7;
+    let index = encrypted.len() - 8 - 16 * 3;
+    encrypted[index + 16] ^= 1;
+
+    let mut reader = ChunkedReader::new(Cursor::new(&encrypted), KeystreamOffsetter::new("chunked")).unwrap();
+    let mut chunk = Vec::new();
+    for i in 0..2 {
+        match reader.read_chunk(i, &mut chunk) {
+            Err(Error::InvalidHeader) => (),
+            r => panic!("expected InvalidHeader for chunk {}, got {:?}", i, r),
+        }
+    }
+    reader.read_chunk(2, &mut chunk).unwrap();
+    assert_eq!(chunk, &TEST_DATA[2000..]);
+}
@@ -0,0 +1,85 @@
+#![cfg(feature = "clap")]
+
+use std::io::Cursor;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+
+use delta_l::header::{self, Kdf, Metadata, Options};
+
+const TEST_DATA: &[u8] = include_bytes!("data/test_data.bin");
+
+fn delta_l(args: &[&Path]) {
+    let status = Command::new(env!("CARGO_BIN_EXE_delta-l")).args(args).arg("-y").arg("-p").arg("cli").status().unwrap();
+    assert!(status.success());
+}
+
+/// A directory of its own for each test
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("delta-l-cli-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn decrypts_to_original_name(){
+    let dir = temp_dir("name");
+    let original = dir.join("original.bin");
+    fs::write(&original, TEST_DATA).unwrap();
+    #[cfg(unix)]
+    fs::set_permissions(&original, PermissionsExt::from_mode(0o4750)).unwrap();
+
+    delta_l(&["e".as_ref(), &original]);
+    fs::remove_file(&original).unwrap();
+    delta_l(&["d".as_ref(), &dir.join("original.bin.delta")]);
+
+    let info = header::read_header(&mut fs::File::open(dir.join("original.bin.delta")).unwrap()).unwrap();
+    assert!(info.authenticated && !info.has_checksum);
+
+    assert_eq!(fs::read(&original).unwrap(), TEST_DATA);
+    // Without the setuid bit
+    #[cfg(unix)]
+    assert_eq!(fs::metadata(&original).unwrap().permissions().mode() & 0o7777, 0o750);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Writes a file encrypted with the passphrase the tests use, with `name` as the original name
+fn encrypted_with_name(dir: &Path, name: &str, options: Options) -> PathBuf {
+    let metadata = Metadata {
+        name: Some(name.to_owned()),
+        ..Metadata::default()
+    };
+    let mut encrypted = Cursor::new(Vec::new());
+    header::encode_v2_with_passphrase("cli", Kdf::Keystream{rounds: 10}, &options.metadata(metadata), &mut &*TEST_DATA, &mut encrypted).unwrap();
+    let path = dir.join("encrypted.delta");
+    fs::write(&path, encrypted.into_inner()).unwrap();
+    path
+}
+
+#[test]
+fn original_name_stays_in_directory(){
+    let dir = temp_dir("escape");
+    let encrypted = encrypted_with_name(&dir, "../escaped.bin", Options::new().authenticate(true));
+
+    delta_l(&["d".as_ref(), &encrypted]);
+
+    assert_eq!(fs::read(dir.join("escaped.bin")).unwrap(), TEST_DATA);
+    assert!(!dir.with_file_name("escaped.bin").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn unauthenticated_name_is_ignored(){
+    let dir = temp_dir("unauthenticated");
+    let victim = dir.join("victim.txt");
+    fs::write(&victim, b"keep me").unwrap();
+    let encrypted = encrypted_with_name(&dir, "victim.txt", Options::new());
+
+    delta_l(&["d".as_ref(), &encrypted]);
+
+    assert_eq!(fs::read(&victim).unwrap(), b"keep me");
+    assert_eq!(fs::read(dir.join("encrypted.delta.dec")).unwrap(), TEST_DATA);
+    fs::remove_dir_all(&dir).unwrap();
+}
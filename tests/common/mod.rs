@@ -0,0 +1,9 @@
+use delta_l::{Delta, Offset};
+
+/// `data` encoded in one go, to compare the other ways of encoding against
+pub fn encoded<O: Offset>(delta: Delta, data: &[u8], mut offsetter: O) -> Vec<u8> {
+    let mut buf = data.to_vec();
+    let mut delta = delta;
+    delta.encode(&mut buf, &mut offsetter);
+    buf
+}
@@ -1,3 +1,5 @@
+#![allow(clippy::redundant_static_lifetimes)]
+
 use std::io::Cursor;
 
 const TEST_DATA: &'static[u8] = include_bytes!("data/test_data.bin");
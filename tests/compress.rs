@@ -0,0 +1,111 @@
+#![cfg(feature = "compress")]
+
+use std::io::{Cursor, Read, Write};
+
+use delta_l::KeystreamOffsetter;
+use delta_l::header::{self, Error, Kdf, Options, Encoder, Decoder};
+
+const TEST_DATA: &[u8] = include_bytes!("data/test_data.bin");
+
+fn options() -> Vec<Options> {
+    vec![
+        Options::new().compress(true),
+        Options::new().compress(true).checksum(false),
+        Options::new().compress(true).authenticate(true).length(true),
+    ]
+}
+
+/// Text that repeats, with runs long enough to need extra length bytes
+fn compressible() -> Vec<u8> {
+    let mut data = Vec::new();
+    for i in 0..5000 {
+        writeln!(data, "line {} of some text that repeats", i % 37).unwrap();
+    }
+    data.extend_from_slice(&[b'a'; 100_000]);
+    data.extend_from_slice(TEST_DATA);
+    data
+}
+
+fn round_trip(data: &[u8], options: &Options) -> Vec<u8> {
+    let mut encrypted = Cursor::new(Vec::new());
+    header::encode_v2(KeystreamOffsetter::new("compress"), options, &mut &*data, &mut encrypted).unwrap();
+    let encrypted = encrypted.into_inner();
+
+    let mut dec = Vec::new();
+    delta_l::decode(KeystreamOffsetter::new("compress"), &mut &*encrypted, &mut dec).unwrap();
+    assert_eq!(dec, data);
+
+    let mut dec = Vec::new();
+    delta_l::decode_verified(KeystreamOffsetter::new("compress"), &mut Cursor::new(&encrypted), &mut dec).unwrap();
+    assert_eq!(dec, data);
+
+    let mut dec = Vec::new();
+    Decoder::new(&*encrypted, KeystreamOffsetter::new("compress")).read_to_end(&mut dec).unwrap();
+    assert_eq!(dec, data);
+
+    encrypted
+}
+
+#[test]
+fn compressed_round_trip(){
+    let data = compressible();
+    for options in options() {
+        let encrypted = round_trip(&data, &options);
+        assert!(encrypted.len() < data.len() / 4);
+        assert!(header::read_header(&mut &*encrypted).unwrap().compressed);
+
+        round_trip(TEST_DATA, &options);
+        round_trip(b"", &options);
+    }
+}
+
+#[test]
+fn streaming_round_trip(){
+    let data = compressible();
+    for options in options() {
+        let mut encrypted = Vec::new();
+        header::encode_v2_streaming_with_passphrase("compress", Kdf::Keystream{rounds: 10}, &options, &mut &*data, &mut encrypted).unwrap();
+
+        let mut dec = Vec::new();
+        header::decode_with_passphrase("compress", &mut &*encrypted, &mut dec).unwrap();
+        assert_eq!(dec, data);
+
+        let mut encoder = Encoder::new(Vec::new(), KeystreamOffsetter::new("compress"), &options).unwrap();
+        // Small writes that don't line up with the blocks
+        for chunk in data.chunks(1000) {
+            encoder.write_all(chunk).unwrap();
+        }
+        let encrypted = encoder.finish().unwrap();
+
+        let mut dec = Vec::new();
+        Decoder::new(&*encrypted, KeystreamOffsetter::new("compress")).read_to_end(&mut dec).unwrap();
+        assert_eq!(dec, data);
+
+        let mut dec = Vec::new();
+        delta_l::decode_verified(KeystreamOffsetter::new("compress"), &mut Cursor::new(&encrypted), &mut dec).unwrap();
+        assert_eq!(dec, data);
+    }
+}
+
+#[test]
+fn corrupted(){
+    let data = compressible();
+    let mut encrypted = Cursor::new(Vec::new());
+    header::encode_v2(KeystreamOffsetter::new("compress"), &Options::new().compress(true), &mut &*data, &mut encrypted).unwrap();
+    let mut encrypted = encrypted.into_inner();
+    let len = encrypted.len();
+    encrypted[len / 2] ^= 0x55;
+
+    match delta_l::decode_verified(KeystreamOffsetter::new("compress"), &mut Cursor::new(&encrypted), &mut Vec::new()) {
+        Err(Error::ChecksumMismatch) => (),
+        r => panic!("expected ChecksumMismatch, got {:?}", r),
+    }
+}
+
+#[test]
+fn not_chunked(){
+    match header::encode_parallel(KeystreamOffsetter::new("compress"), 1000, 2, &Options::new().compress(true), &mut &*TEST_DATA, &mut Vec::new()) {
+        Err(Error::Unsupported) => (),
+        r => panic!("expected Unsupported, got {:?}", r),
+    }
+}
@@ -1,3 +1,5 @@
+#![allow(clippy::redundant_static_lifetimes)]
+
 use std::io::Cursor;
 
 const TEST_DATA: &'static[u8] = b"Hello, yes I'll be used for this test!";
@@ -12,6 +14,11 @@ fn decrypt_is_orignal_with_password(){
     test(delta_l::PassHashOffsetter::new("hejsa!"))
 }
 
+#[test]
+fn decrypt_is_orignal_with_keystream(){
+    test(delta_l::KeystreamOffsetter::new("hejsa!"))
+}
+
 fn test<T: Copy + delta_l::Offset>(passhash: T) {
     let test_data = TEST_DATA.to_vec();
 
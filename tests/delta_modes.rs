@@ -0,0 +1,213 @@
+mod common;
+
+use std::io::{BufRead, BufReader, Cursor, Read, Write, Seek, SeekFrom};
+
+use delta_l::{Delta, Word, Combine, DeltaWriter, DeltaReader, KeystreamOffsetter, MAX_ORDER, MAX_STRIDE};
+use delta_l::header::{self, Error, Options, Encoder, Decoder, ChunkedReader};
+use common::encoded;
+
+const TEST_DATA: &[u8] = include_bytes!("data/test_data.bin");
+
+/// An odd length, which leaves a tail that isn't a whole word for every width
+const DATA_LEN: usize = 2043;
+
+/// The encodings that are checked the same way, which all differ from the plain one
+fn deltas() -> Vec<Delta> {
+    vec![
+        Delta::with_order(2),
+        Delta::with_order(MAX_ORDER),
+        Delta::new().with_word(Word::U16Le),
+        Delta::with_order(2).with_word(Word::U16Be),
+        Delta::new().with_word(Word::U32Le),
+        Delta::with_order(3).with_word(Word::U32Be),
+        Delta::new().with_word(Word::U64Le),
+        Delta::new().with_word(Word::U64Be),
+        Delta::new().with_stride(3),
+        Delta::with_order(3).with_stride(4),
+        Delta::with_order(2).with_word(Word::U32Be).with_stride(2),
+        Delta::new().with_word(Word::U16Le).with_stride(MAX_STRIDE),
+        Delta::new().with_combine(Combine::Xor),
+        Delta::with_order(3).with_combine(Combine::Xor),
+        Delta::new().with_word(Word::U32Be).with_stride(2).with_combine(Combine::Xor),
+    ]
+}
+
+fn offsetter() -> KeystreamOffsetter {
+    KeystreamOffsetter::new("delta modes")
+}
+
+#[test]
+fn differs_from_plain(){
+    let data = &TEST_DATA[..DATA_LEN];
+    let plain = encoded(Delta::new(), data, offsetter());
+    for delta in deltas() {
+        assert_ne!(encoded(delta, data, offsetter()), plain, "{:?}", delta);
+    }
+}
+
+#[test]
+fn writer_reader_round_trip(){
+    let data = &TEST_DATA[..DATA_LEN];
+    for delta in deltas() {
+        let expected = encoded(delta, data, offsetter());
+
+        let mut writer = DeltaWriter::with_delta(Vec::new(), offsetter(), delta);
+        for piece in data.chunks(7) {
+            writer.write_all(piece).unwrap();
+        }
+        assert_eq!(writer.finish().unwrap(), expected, "{:?}", delta);
+
+        let mut reader = DeltaReader::with_delta(&*expected, offsetter(), delta);
+        let mut dec = vec![0; 5];
+        reader.read_exact(&mut dec).unwrap();
+        reader.read_to_end(&mut dec).unwrap();
+        assert_eq!(dec, data, "{:?}", delta);
+
+        let mut reader = DeltaReader::with_delta(BufReader::with_capacity(7, &*expected), offsetter(), delta);
+        let mut dec = Vec::new();
+        loop {
+            let buf = reader.fill_buf().unwrap();
+            if buf.is_empty() {
+                break
+            }
+            let n = buf.len().min(11);
+            dec.extend_from_slice(&buf[..n]);
+            reader.consume(n);
+        }
+        assert_eq!(dec, data, "{:?}", delta);
+    }
+}
+
+#[test]
+fn header_round_trip(){
+    let data = &TEST_DATA[..DATA_LEN];
+    for delta in deltas() {
+        let options = Options::new().authenticate(true).delta(delta);
+
+        let mut encrypted = Cursor::new(Vec::new());
+        header::encode_v2(offsetter(), &options, &mut &*data, &mut encrypted).unwrap();
+        let encrypted = encrypted.into_inner();
+        assert_eq!(header::read_header(&mut &*encrypted).unwrap().delta, delta);
+
+        let mut dec = Vec::new();
+        delta_l::decode(offsetter(), &mut &*encrypted, &mut dec).unwrap();
+        assert_eq!(dec, data, "{:?}", delta);
+        let mut dec = Vec::new();
+        delta_l::decode_verified(offsetter(), &mut Cursor::new(&encrypted), &mut dec).unwrap();
+        assert_eq!(dec, data, "{:?}", delta);
+
+        let mut encoder = Encoder::new(Vec::new(), offsetter(), &options).unwrap();
+        for piece in data.chunks(3) {
+            encoder.write_all(piece).unwrap();
+        }
+        let streamed = encoder.finish().unwrap();
+        let mut dec = Vec::new();
+        Decoder::new(&*streamed, offsetter()).read_to_end(&mut dec).unwrap();
+        assert_eq!(dec, data, "{:?}", delta);
+    }
+}
+
+#[test]
+fn chunked_round_trip(){
+    for delta in deltas() {
+        let options = Options::new().delta(delta);
+
+        // Chunks that aren't whole words end in a tail of their own,
+        // and ones that aren't a whole number of words for each channel start over at the first channel
+        let mut sequential = Vec::new();
+        header::encode_chunked(offsetter(), 999, &options, &mut &*TEST_DATA, &mut sequential).unwrap();
+        let mut parallel = Vec::new();
+        header::encode_parallel(offsetter(), 999, 3, &options, &mut &*TEST_DATA, &mut parallel).unwrap();
+        assert_eq!(parallel, sequential, "{:?}", delta);
+
+        let mut dec = Vec::new();
+        header::decode_parallel(offsetter(), 3, &mut Cursor::new(&parallel), &mut dec).unwrap();
+        assert_eq!(dec, TEST_DATA, "{:?}", delta);
+        let mut dec = Vec::new();
+        Decoder::new(&*sequential, offsetter()).read_to_end(&mut dec).unwrap();
+        assert_eq!(dec, TEST_DATA, "{:?}", delta);
+
+        let mut reader = ChunkedReader::new(Cursor::new(&sequential), offsetter()).unwrap();
+        let mut chunk = Vec::new();
+        reader.read_chunk(1, &mut chunk).unwrap();
+        assert_eq!(chunk, &TEST_DATA[999..1998], "{:?}", delta);
+    }
+}
+
+#[test]
+fn reader_seek(){
+    let data = &TEST_DATA[..DATA_LEN];
+    for delta in deltas() {
+        let mut reader = DeltaReader::with_delta(Cursor::new(encoded(delta, data, offsetter())), offsetter(), delta);
+
+        let mut buf = [0; 5];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(reader.seek(SeekFrom::Current(10)).unwrap(), 15);
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data[15..20], "{:?}", delta);
+
+        for &pos in &[1001, 16, 3, 0, 2041] {
+            reader.seek(SeekFrom::Start(pos)).unwrap();
+            let mut rest = Vec::new();
+            reader.read_to_end(&mut rest).unwrap();
+            assert_eq!(rest, &data[pos as usize..], "{:?} from {}", delta, pos);
+        }
+    }
+}
+
+/// Gives back at most three bytes for each read, so that reads end in the middle of a word
+struct ShortReads(Cursor<Vec<u8>>);
+
+impl Read for ShortReads {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(3);
+        self.0.read(&mut buf[..len])
+    }
+}
+
+impl Seek for ShortReads {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.0.seek(pos)
+    }
+}
+
+#[test]
+fn reader_seek_short_reads(){
+    let data = &TEST_DATA[..DATA_LEN];
+    for delta in deltas() {
+        let mut reader = DeltaReader::with_delta(ShortReads(Cursor::new(encoded(delta, data, offsetter()))), offsetter(), delta);
+
+        let mut buf = [0; 5];
+        reader.read_exact(&mut buf[..1]).unwrap();
+        assert_eq!(reader.seek(SeekFrom::Current(9)).unwrap(), 10);
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data[10..15], "{:?}", delta);
+
+        assert_eq!(reader.seek(SeekFrom::Current(-4)).unwrap(), 11);
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data[11..16], "{:?}", delta);
+    }
+}
+
+#[test]
+fn unsupported_values(){
+    // Each extension field with a value that isn't supported: the tag, the length and the data, and what to change
+    for &(delta, field, bad) in &[
+        (Delta::with_order(2), &[7, 1, 0, 2][..], MAX_ORDER + 1),
+        (Delta::new().with_word(Word::U16Le), &[8, 2, 0, 2, 0][..], 3),
+        (Delta::new().with_stride(4), &[9, 1, 0, 4][..], MAX_STRIDE + 1),
+        (Delta::new().with_combine(Combine::Xor), &[10, 1, 0, 1][..], 2),
+    ] {
+        let mut encrypted = Cursor::new(Vec::new());
+        header::encode_v2(offsetter(), &Options::new().delta(delta), &mut &*TEST_DATA, &mut encrypted).unwrap();
+        let mut encrypted = encrypted.into_inner();
+
+        let pos = encrypted.windows(field.len()).position(|w| w == field).unwrap();
+        encrypted[pos + 3] = bad;
+
+        match delta_l::decode(offsetter(), &mut &*encrypted, &mut Vec::new()) {
+            Err(Error::Unsupported) => (),
+            res => panic!("expected Unsupported for {:?}, got {:?}", delta, res),
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use std::io::{Cursor, Read};
+
+use delta_l::PassHashOffsetter;
+use delta_l::header::{self, Kdf, Options, Variant};
+
+const TEST_DATA: &[u8] = include_bytes!("data/test_data.bin");
+
+#[test]
+fn legacy(){
+    let mut encrypted = Cursor::new(Vec::new());
+    delta_l::encode_with_checksum(PassHashOffsetter::new("info"), &mut &*TEST_DATA, &mut encrypted).unwrap();
+    let encrypted = encrypted.into_inner();
+
+    let mut src = &*encrypted;
+    let info = header::read_header(&mut src).unwrap();
+    assert_eq!(info.variant, Variant::Checksum);
+    assert_eq!(info.header_len, 12);
+    assert!(info.has_checksum);
+    assert_eq!(info.checksum.unwrap().to_le_bytes(), encrypted[4..12]);
+    assert_eq!(info.kdf, None);
+    assert_eq!(src.len(), TEST_DATA.len());
+
+    let mut encrypted = Vec::new();
+    header::encode_salted_no_checksum("info", 10, &mut &*TEST_DATA, &mut encrypted).unwrap();
+    let info = header::read_header(&mut &*encrypted).unwrap();
+    assert_eq!(info.variant, Variant::SaltedNoChecksum);
+    assert_eq!(info.header_len, 24);
+    assert!(!info.has_checksum);
+    assert_eq!(info.checksum, None);
+    assert_eq!(info.kdf, Some(Kdf::PassHash{rounds: 10}));
+}
+
+#[test]
+fn v2(){
+    let options = Options::new().authenticate(true).extension(0x90, b"data".to_vec());
+    let mut encrypted = Cursor::new(Vec::new());
+    header::encode_v2_with_passphrase("info", Kdf::Keystream{rounds: 10}, &options, &mut &*TEST_DATA, &mut encrypted).unwrap();
+    let encrypted = encrypted.into_inner();
+
+    let mut src = Cursor::new(&encrypted);
+    let info = header::read_header(&mut src).unwrap();
+    assert_eq!(info.variant, Variant::V2);
+    assert_eq!(info.header_len, src.position());
+    assert_eq!(info.header_len as usize, encrypted.len() - TEST_DATA.len());
+    // The tag replaces the checksum, which would give away whether a guess of the plaintext is right
+    assert!(!info.has_checksum && info.checksum.is_none());
+    assert!(info.authenticated && info.tag.is_some());
+    assert!(!info.trailer);
+    assert_eq!(info.kdf, Some(Kdf::Keystream{rounds: 10}));
+    assert!(info.salt.is_some());
+    assert_eq!(info.extensions, vec![(0x90, b"data".to_vec())]);
+
+    let mut payload = Vec::new();
+    src.read_to_end(&mut payload).unwrap();
+    assert_eq!(payload.len(), TEST_DATA.len());
+}
+
+#[test]
+fn trailer(){
+    let mut encrypted = Vec::new();
+    header::encode_v2_streaming(PassHashOffsetter::new("info"), &Options::new(), &mut &*TEST_DATA, &mut encrypted).unwrap();
+
+    let info = header::read_header(&mut &*encrypted).unwrap();
+    assert!(info.trailer);
+    assert!(info.has_checksum);
+    assert_eq!(info.checksum, None);
+    assert_eq!(info.header_len as usize + TEST_DATA.len() + 8, encrypted.len());
+}
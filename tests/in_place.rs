@@ -0,0 +1,90 @@
+use std::io::Write;
+
+use delta_l::{Offset, DeltaWriter, PassHashOffsetter, encode_in_place, decode_in_place};
+
+const TEST_DATA: &[u8] = include_bytes!("data/test_data.bin");
+
+#[test]
+fn in_place_matches_writer(){
+    let mut writer = DeltaWriter::with_offsetter(Vec::new(), PassHashOffsetter::new("in place"));
+    writer.write_all(TEST_DATA).unwrap();
+    let expected = writer.into_inner();
+
+    let mut buf = TEST_DATA.to_vec();
+    let mut offsetter = PassHashOffsetter::new("in place");
+    let mut last = 0;
+    // Uneven pieces to check that the state carries over between calls
+    let (a, b) = buf.split_at_mut(333);
+    encode_in_place(a, &mut offsetter, &mut last);
+    encode_in_place(b, &mut offsetter, &mut last);
+    assert_eq!(buf, expected);
+
+    let mut offsetter = PassHashOffsetter::new("in place");
+    let mut last = 0;
+    for chunk in buf.chunks_mut(1000) {
+        decode_in_place(chunk, &mut offsetter, &mut last);
+    }
+    assert_eq!(buf, TEST_DATA);
+}
+
+/// Only implements the required methods, so `decode_in_place` can be checked
+/// against the plain byte by byte definition
+struct Counter(u8);
+
+impl Offset for Counter {
+    fn next_offset(&mut self) -> u8 {
+        self.0 = self.0.wrapping_mul(31).wrapping_add(7);
+        self.0
+    }
+    fn step_back(&mut self) {
+        // 223 is the inverse of 31 modulo 256
+        self.0 = self.0.wrapping_sub(7).wrapping_mul(223);
+    }
+    fn reset(&mut self) {
+        self.0 = 0;
+    }
+}
+
+#[test]
+fn decode_in_place_lengths(){
+    for len in 0..200 {
+        for &start in &[0, 1, 3, 8] {
+            let data = &TEST_DATA[start..start + len];
+
+            let mut expected = data.to_vec();
+            let mut offsetter = Counter(0);
+            let mut last = 0x5A;
+            for b in &mut expected {
+                last = b.wrapping_sub(offsetter.next_offset()).wrapping_sub(last);
+                *b = last;
+            }
+
+            let mut buf = data.to_vec();
+            let mut last = 0x5A;
+            decode_in_place(&mut buf, &mut Counter(0), &mut last);
+            assert_eq!(buf, expected);
+            assert_eq!(last, expected.last().cloned().unwrap_or(0x5A));
+
+            let mut counter = Counter(0);
+            counter.skip(len as u64);
+            for _ in 0..len {
+                counter.step_back();
+            }
+            assert_eq!(counter.0, 0);
+
+            let mut pass_expected = data.to_vec();
+            let mut pass_buf = data.to_vec();
+            let mut offsetter = PassHashOffsetter::new("in place");
+            offsetter.set_position(start as u64);
+            let mut last = 0;
+            for b in &mut pass_expected {
+                last = b.wrapping_sub(offsetter.next_offset()).wrapping_sub(last);
+                *b = last;
+            }
+            let mut offsetter = PassHashOffsetter::new("in place");
+            offsetter.set_position(start as u64);
+            decode_in_place(&mut pass_buf, &mut offsetter, &mut 0);
+            assert_eq!(pass_buf, pass_expected);
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::io::{Cursor, Read, Write};
+
+use delta_l::KeystreamOffsetter;
+use delta_l::header::{self, Error, Options, Encoder, Decoder};
+
+const TEST_DATA: &[u8] = include_bytes!("data/test_data.bin");
+
+fn options() -> Vec<Options> {
+    vec![
+        Options::new().length(true),
+        Options::new().length(true).checksum(false),
+        Options::new().length(true).authenticate(true),
+    ]
+}
+
+fn encode(options: &Options) -> Vec<u8> {
+    let mut encrypted = Cursor::new(Vec::new());
+    header::encode_v2(KeystreamOffsetter::new("length"), options, &mut &*TEST_DATA, &mut encrypted).unwrap();
+    encrypted.into_inner()
+}
+
+fn decode_all(encrypted: &[u8]) -> [Result<Vec<u8>, Error>; 3] {
+    let mut dec = Vec::new();
+    let decoded = delta_l::decode(KeystreamOffsetter::new("length"), &mut &*encrypted, &mut dec).map(|()| dec);
+    let mut dec = Vec::new();
+    let verified = delta_l::decode_verified(KeystreamOffsetter::new("length"), &mut Cursor::new(encrypted), &mut dec).map(|()| dec);
+    let mut dec = Vec::new();
+    let streamed = Decoder::new(encrypted, KeystreamOffsetter::new("length")).read_to_end(&mut dec).map(|_| dec).map_err(Error::from);
+
+    [decoded, verified, streamed]
+}
+
+#[test]
+fn round_trip(){
+    for options in options() {
+        for res in decode_all(&encode(&options)) {
+            assert_eq!(res.unwrap(), TEST_DATA);
+        }
+
+        let mut encrypted = Vec::new();
+        header::encode_v2_streaming(KeystreamOffsetter::new("length"), &options, &mut &*TEST_DATA, &mut encrypted).unwrap();
+        for res in decode_all(&encrypted) {
+            assert_eq!(res.unwrap(), TEST_DATA);
+        }
+    }
+}
+
+#[test]
+fn length_in_header(){
+    let encrypted = encode(&Options::new().length(true));
+    let info = header::read_header(&mut &*encrypted).unwrap();
+    assert!(info.has_length);
+    assert_eq!(info.length, Some(TEST_DATA.len() as u64));
+}
+
+#[test]
+fn truncated(){
+    for options in options() {
+        let mut encrypted = encode(&options);
+        encrypted.truncate(encrypted.len() - 10);
+
+        for res in decode_all(&encrypted) {
+            match res {
+                Err(Error::Truncated{expected, actual}) => {
+                    assert_eq!(expected, TEST_DATA.len() as u64);
+                    assert_eq!(actual, TEST_DATA.len() as u64 - 10);
+                }
+                r => panic!("expected Truncated, got {:?}", r.map(|_| ())),
+            }
+        }
+    }
+}
+
+#[test]
+fn trailing_data(){
+    for options in options() {
+        let mut encrypted = encode(&options);
+        encrypted.extend_from_slice(b"trailing");
+
+        for res in decode_all(&encrypted) {
+            match res {
+                Err(Error::TrailingData) => (),
+                r => panic!("expected TrailingData, got {:?}", r.map(|_| ())),
+            }
+        }
+    }
+}
+
+#[test]
+fn streaming_encoder(){
+    let mut encoder = Encoder::new(Vec::new(), KeystreamOffsetter::new("length"), &Options::new().length(true)).unwrap();
+    encoder.write_all(TEST_DATA).unwrap();
+    let mut encrypted = encoder.finish().unwrap();
+
+    let info = header::read_header(&mut &*encrypted).unwrap();
+    assert!(info.has_length && info.trailer);
+    for res in decode_all(&encrypted) {
+        assert_eq!(res.unwrap(), TEST_DATA);
+    }
+
+    // Cutting the payload out of the middle moves the trailer with it
+    let trailer = encrypted.split_off(encrypted.len() - 16);
+    encrypted.truncate(encrypted.len() - 10);
+    encrypted.extend_from_slice(&trailer);
+    for res in decode_all(&encrypted) {
+        match res {
+            Err(Error::Truncated{expected, actual}) => assert_eq!(expected - actual, 10),
+            r => panic!("expected Truncated, got {:?}", r.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use std::io::Cursor;
+use std::fs::{self, File};
+use std::time::{Duration, UNIX_EPOCH};
+
+use delta_l::PassHashOffsetter;
+use delta_l::header::{self, Metadata, Options};
+
+const TEST_DATA: &[u8] = include_bytes!("data/test_data.bin");
+
+fn stored(metadata: Metadata) -> Metadata {
+    let mut encrypted = Cursor::new(Vec::new());
+    header::encode_v2(PassHashOffsetter::new("metadata"), &Options::new().metadata(metadata), &mut &*TEST_DATA, &mut encrypted).unwrap();
+
+    let mut dec = Vec::new();
+    delta_l::decode(PassHashOffsetter::new("metadata"), &mut &**encrypted.get_ref(), &mut dec).unwrap();
+    assert_eq!(dec, TEST_DATA);
+
+    header::read_header(&mut &**encrypted.get_ref()).unwrap().metadata
+}
+
+#[test]
+fn round_trip(){
+    let metadata = Metadata {
+        name: Some("tëst_data.bin".to_owned()),
+        size: Some(TEST_DATA.len() as u64),
+        modified: Some(UNIX_EPOCH + Duration::new(1_545_609_600, 123_456_789)),
+        mode: Some(0o640),
+    };
+    assert_eq!(stored(metadata.clone()), metadata);
+
+    let before_epoch = Metadata {
+        modified: Some(UNIX_EPOCH - Duration::new(100, 250)),
+        ..Metadata::default()
+    };
+    assert_eq!(stored(before_epoch.clone()), before_epoch);
+
+    assert_eq!(stored(Metadata::default()), Metadata::default());
+
+    // Setuid, setgid and sticky aren't stored
+    let special = Metadata {
+        mode: Some(0o7755),
+        ..Metadata::default()
+    };
+    assert_eq!(stored(special).mode, Some(0o755));
+}
+
+#[test]
+fn from_file(){
+    let metadata = Metadata::from_file("tests/data/test_data.bin").unwrap();
+    assert_eq!(metadata.name.as_ref().unwrap(), "test_data.bin");
+    assert_eq!(metadata.size, Some(TEST_DATA.len() as u64));
+    assert!(metadata.modified.is_some());
+    assert!(metadata.mode.is_some());
+}
+
+#[test]
+fn apply(){
+    let path = std::env::temp_dir().join(format!("delta-l-metadata-{}", std::process::id()));
+    let file = File::create(&path).unwrap();
+
+    let metadata = Metadata {
+        modified: Some(UNIX_EPOCH + Duration::from_secs(1_545_609_600)),
+        // The header isn't authenticated, so the special bits are never set from it
+        mode: Some(0o6750),
+        ..Metadata::default()
+    };
+    metadata.apply(&file).unwrap();
+    drop(file);
+
+    let applied = fs::metadata(&path).unwrap();
+    assert_eq!(applied.modified().unwrap(), metadata.modified.unwrap());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(applied.permissions().mode() & 0o7777, 0o750);
+    }
+    fs::remove_file(&path).unwrap();
+}
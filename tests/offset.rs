@@ -0,0 +1,85 @@
+use delta_l::{Offset, PassHashOffsetter, KeystreamOffsetter, ZeroOffset};
+
+/// Only implements the required methods, so the default implementations get used
+struct Counter(u8);
+
+impl Offset for Counter {
+    fn next_offset(&mut self) -> u8 {
+        self.0 = self.0.wrapping_add(1);
+        self.0
+    }
+    fn step_back(&mut self) {
+        self.0 = self.0.wrapping_sub(1);
+    }
+    fn reset(&mut self) {
+        self.0 = 0;
+    }
+}
+
+fn check_positioning<O: Offset>(mut a: O, mut b: O) {
+    a.reset();
+    b.reset();
+    for _ in 0..1000 {
+        a.next_offset();
+    }
+    b.skip(1000);
+    assert_eq!(a.next_offset(), b.next_offset());
+
+    a.set_position(3);
+    b.reset();
+    b.skip(3);
+    for _ in 0..20 {
+        assert_eq!(a.next_offset(), b.next_offset());
+    }
+}
+
+#[test]
+fn pass_hash_position(){
+    let mut offsetter = PassHashOffsetter::new("position");
+    assert_eq!(offsetter.position(), Some(0));
+    offsetter.skip(13);
+    offsetter.next_offset();
+    offsetter.step_back();
+    assert_eq!(offsetter.position(), Some(13));
+
+    check_positioning(offsetter, PassHashOffsetter::new("position"));
+}
+
+#[test]
+fn default_position(){
+    assert_eq!(Counter(0).position(), None);
+    check_positioning(Counter(0), Counter(0));
+    check_positioning(ZeroOffset, ZeroOffset);
+}
+
+#[test]
+fn keystream_position(){
+    let mut offsetter = KeystreamOffsetter::new("keystream");
+    let offsets: Vec<u8> = (0..64).map(|_| offsetter.next_offset()).collect();
+    assert_ne!(offsets[..8], offsets[8..16]);
+    assert_ne!(offsets[..16], offsets[16..32]);
+
+    offsetter.step_back();
+    offsetter.step_back();
+    assert_eq!(offsetter.position(), Some(62));
+    assert_eq!(offsetter.next_offset(), offsets[62]);
+    offsetter.set_position(5);
+    assert_eq!(offsetter.next_offset(), offsets[5]);
+
+    check_positioning(offsetter, KeystreamOffsetter::new("keystream"));
+}
+
+#[test]
+fn keystream_sub_offsets(){
+    let mut a = KeystreamOffsetter::new("keystream");
+    let mut b = KeystreamOffsetter::new("keystream");
+    a.skip(5);
+    b.skip(5);
+
+    let mut buf = [0; 50];
+    a.sub_offsets(&mut buf);
+    for &o in &buf[..] {
+        assert_eq!(o, b.next_offset().wrapping_neg());
+    }
+    assert_eq!(a.position(), b.position());
+}
@@ -0,0 +1,44 @@
+use std::io::{Cursor, Write};
+
+use delta_l::{Delta, DeltaWriter, KeystreamOffsetter, ZeroOffset, MAX_ORDER};
+use delta_l::header::{self, Options};
+
+const TEST_DATA: &[u8] = include_bytes!("data/test_data.bin");
+
+fn encode_writer(delta: Delta) -> Vec<u8> {
+    let mut writer = DeltaWriter::with_delta(Vec::new(), KeystreamOffsetter::new("order"), delta);
+    let (a, b) = TEST_DATA.split_at(777);
+    writer.write_all(a).unwrap();
+    writer.write_all(b).unwrap();
+    writer.into_inner()
+}
+
+#[test]
+fn known_vector(){
+    let mut buf = [1, 2, 3, 4];
+    Delta::with_order(2).encode(&mut buf, &mut ZeroOffset);
+    // 1, 2 + 1, 3 + 2, 4 + 3 summed again
+    assert_eq!(buf, [1, 4, 8, 12]);
+
+    Delta::with_order(2).decode(&mut buf, &mut ZeroOffset);
+    assert_eq!(buf, [1, 2, 3, 4]);
+}
+
+#[test]
+fn order_one_is_plain(){
+    let mut writer = DeltaWriter::with_offsetter(Vec::new(), KeystreamOffsetter::new("order"));
+    writer.write_all(TEST_DATA).unwrap();
+    assert_eq!(encode_writer(Delta::with_order(1)), writer.into_inner());
+
+    let mut plain = Cursor::new(Vec::new());
+    header::encode_v2(KeystreamOffsetter::new("order"), &Options::new(), &mut &*TEST_DATA, &mut plain).unwrap();
+    let mut order_one = Cursor::new(Vec::new());
+    header::encode_v2(KeystreamOffsetter::new("order"), &Options::new().delta(Delta::with_order(1)), &mut &*TEST_DATA, &mut order_one).unwrap();
+    assert_eq!(order_one.into_inner(), plain.into_inner());
+}
+
+#[test]
+#[should_panic]
+fn order_too_high(){
+    Delta::with_order(MAX_ORDER + 1);
+}
@@ -0,0 +1,83 @@
+use std::io::{Cursor, ErrorKind};
+
+use delta_l::{PassHashOffsetter, KeystreamOffsetter};
+use delta_l::header::{self, Error, Kdf, Options};
+
+const TEST_DATA: &[u8] = include_bytes!("data/test_data.bin");
+
+#[test]
+fn same_as_sequential(){
+    for &chunk_size in &[100, 1000, 1024, 4096] {
+        let mut sequential = Vec::new();
+        header::encode_chunked(KeystreamOffsetter::new("parallel"), chunk_size, &Options::new(), &mut &*TEST_DATA, &mut sequential).unwrap();
+
+        for threads in 1..5 {
+            let mut parallel = Vec::new();
+            header::encode_parallel(KeystreamOffsetter::new("parallel"), chunk_size, threads, &Options::new(), &mut &*TEST_DATA, &mut parallel).unwrap();
+            assert_eq!(parallel, sequential);
+
+            let mut dec = Vec::new();
+            header::decode_parallel(KeystreamOffsetter::new("parallel"), threads, &mut Cursor::new(&parallel), &mut dec).unwrap();
+            assert_eq!(dec, TEST_DATA);
+        }
+    }
+}
+
+#[test]
+fn passphrase_round_trip(){
+    let mut encrypted = Vec::new();
+    header::encode_parallel_with_passphrase("parallel", Kdf::Keystream{rounds: 10}, 300, 4, &Options::new(), &mut &*TEST_DATA, &mut encrypted).unwrap();
+
+    let mut dec = Vec::new();
+    header::decode_parallel_with_passphrase("parallel", 3, &mut Cursor::new(&encrypted), &mut dec).unwrap();
+    assert_eq!(dec, TEST_DATA);
+
+    let mut dec = Vec::new();
+    delta_l::decode_with_passphrase("parallel", &mut &*encrypted, &mut dec).unwrap();
+    assert_eq!(dec, TEST_DATA);
+}
+
+#[test]
+fn checked_before_writing(){
+    let mut encrypted = Vec::new();
+    header::encode_parallel(PassHashOffsetter::new("parallel"), 300, 4, &Options::new(), &mut &*TEST_DATA, &mut encrypted).unwrap();
+    let len = encrypted.len();
+    // In the last chunk
+    encrypted[len - 8 - 16 * 7 - 12 - 10] ^= 1;
+
+    let mut dec = Vec::new();
+    match header::decode_parallel(PassHashOffsetter::new("parallel"), 4, &mut Cursor::new(&encrypted), &mut dec) {
+        Err(Error::ChecksumMismatch) => (),
+        r => panic!("expected ChecksumMismatch, got {:?}", r),
+    }
+    assert!(dec.is_empty());
+}
+
+#[test]
+fn not_chunked(){
+    let mut encrypted = Cursor::new(Vec::new());
+    header::encode_v2(PassHashOffsetter::new("parallel"), &Options::new(), &mut &*TEST_DATA, &mut encrypted).unwrap();
+    encrypted.set_position(0);
+
+    let mut dec = Vec::new();
+    header::decode_parallel(PassHashOffsetter::new("parallel"), 4, &mut encrypted, &mut dec).unwrap();
+    assert_eq!(dec, TEST_DATA);
+}
+
+#[test]
+fn buffers_follow_the_data(){
+    // Only the chunks there's data for are allocated, not 10000 of 16 MiB
+    let mut encrypted = Vec::new();
+    header::encode_parallel(KeystreamOffsetter::new("parallel"), 1 << 24, 10_000, &Options::new(), &mut &*TEST_DATA, &mut encrypted).unwrap();
+
+    // The chunk size extension field and the length of the only record, claiming far more than there is
+    let pos = encrypted.windows(7).position(|w| w == [2, 4, 0, 0, 0, 0, 1]).unwrap();
+    encrypted[pos + 3..pos + 7].copy_from_slice(&[0xFF; 4]);
+    let pos = pos + encrypted[pos..].windows(4).position(|w| w == [0, 8, 0, 0]).unwrap();
+    encrypted[pos..pos + 4].copy_from_slice(&[0xF0, 0xFF, 0xFF, 0xFF]);
+
+    match header::decode_parallel(KeystreamOffsetter::new("parallel"), 10_000, &mut Cursor::new(&encrypted), &mut Vec::new()) {
+        Err(Error::Io(ref e)) if e.kind() == ErrorKind::UnexpectedEof => (),
+        r => panic!("expected UnexpectedEof, got {:?}", r),
+    }
+}
@@ -0,0 +1,80 @@
+use std::io::Cursor;
+
+use delta_l::MAX_ROUNDS;
+use delta_l::header::{self, Error, Kdf, Options};
+
+const TEST_DATA: &[u8] = b"Hello, yes I'll be used for this test!";
+
+#[test]
+fn salted_round_trip(){
+    let mut with_checksum = Cursor::new(Vec::new());
+    header::encode_salted_with_checksum("salty", 100, &mut &*TEST_DATA, &mut with_checksum).unwrap();
+    let mut no_checksum = Vec::new();
+    header::encode_salted_no_checksum("salty", 100, &mut &*TEST_DATA, &mut no_checksum).unwrap();
+
+    for encrypted in &[with_checksum.into_inner(), no_checksum] {
+        let mut dec = Vec::new();
+        delta_l::decode_with_passphrase("salty", &mut &**encrypted, &mut dec).unwrap();
+        assert_eq!(dec, TEST_DATA);
+
+        match delta_l::decode(delta_l::ZeroOffset, &mut &**encrypted, &mut Vec::new()) {
+            Err(Error::PassphraseRequired) => (),
+            r => panic!("expected PassphraseRequired, got {:?}", r),
+        }
+    }
+}
+
+#[test]
+fn salts_differ(){
+    let mut a = Vec::new();
+    let mut b = Vec::new();
+    header::encode_salted_no_checksum("salty", 100, &mut &*TEST_DATA, &mut a).unwrap();
+    header::encode_salted_no_checksum("salty", 100, &mut &*TEST_DATA, &mut b).unwrap();
+
+    assert_ne!(a, b);
+}
+
+#[test]
+fn wrong_passphrase(){
+    let mut encrypted = Cursor::new(Vec::new());
+    header::encode_salted_with_checksum("salty", 100, &mut &*TEST_DATA, &mut encrypted).unwrap();
+
+    match delta_l::decode_with_passphrase("sweet", &mut &**encrypted.get_ref(), &mut Vec::new()) {
+        Err(Error::ChecksumMismatch) => (),
+        r => panic!("expected ChecksumMismatch, got {:?}", r),
+    }
+}
+
+#[test]
+fn passphrase_decodes_unsalted(){
+    let mut encrypted = Vec::new();
+    delta_l::encode_no_checksum(delta_l::PassHashOffsetter::new("plain"), &mut &*TEST_DATA, &mut encrypted).unwrap();
+
+    let mut dec = Vec::new();
+    delta_l::decode_with_passphrase("plain", &mut &*encrypted, &mut dec).unwrap();
+    assert_eq!(dec, TEST_DATA);
+}
+
+#[test]
+fn too_many_rounds(){
+    let mut salted = Vec::new();
+    header::encode_salted_no_checksum("salty", 100, &mut &*TEST_DATA, &mut salted).unwrap();
+    let mut v2 = Cursor::new(Vec::new());
+    header::encode_v2_with_passphrase("salty", Kdf::Keystream{rounds: 100}, &Options::new(), &mut &*TEST_DATA, &mut v2).unwrap();
+
+    // The rounds come right after the salt, which is after the magic bytes and, in version 2, the version, id and flags
+    for (mut encrypted, pos) in [(salted, 20), (v2.into_inner(), 24)] {
+        encrypted[pos..pos + 4].copy_from_slice(&[0xFF; 4]);
+        match delta_l::decode_with_passphrase("salty", &mut &*encrypted, &mut Vec::new()) {
+            Err(Error::Unsupported) => (),
+            r => panic!("expected Unsupported, got {:?}", r),
+        }
+    }
+
+    let mut encrypted = Vec::new();
+    match header::encode_salted_no_checksum("salty", MAX_ROUNDS + 1, &mut &*TEST_DATA, &mut encrypted) {
+        Err(Error::Unsupported) => (),
+        r => panic!("expected Unsupported, got {:?}", r),
+    }
+    assert!(encrypted.is_empty());
+}
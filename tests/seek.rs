@@ -0,0 +1,91 @@
+use std::io::{Cursor, Read, Write, Seek, SeekFrom};
+
+use delta_l::{DeltaReader, DeltaWriter, PassHashOffsetter};
+
+const TEST_DATA: &[u8] = include_bytes!("data/test_data.bin");
+
+fn encrypted() -> Vec<u8> {
+    let mut writer = DeltaWriter::with_offsetter(Vec::new(), PassHashOffsetter::new("seek"));
+    writer.write_all(TEST_DATA).unwrap();
+    writer.into_inner()
+}
+
+#[test]
+fn reader_seek(){
+    let mut reader = DeltaReader::with_offsetter(Cursor::new(encrypted()), PassHashOffsetter::new("seek"));
+    let len = TEST_DATA.len() as u64;
+
+    for &(pos, expected) in &[
+        (SeekFrom::Start(100), 100),
+        (SeekFrom::Current(-37), 63),
+        (SeekFrom::Current(1000), 1063),
+        (SeekFrom::End(-17), len - 17),
+        (SeekFrom::Start(0), 0),
+        (SeekFrom::End(0), len),
+    ] {
+        assert_eq!(reader.seek(pos).unwrap(), expected);
+
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(&*rest, &TEST_DATA[expected as usize..]);
+        reader.seek(SeekFrom::Start(expected)).unwrap();
+    }
+
+    assert!(reader.seek(SeekFrom::Current(-1 - len as i64)).is_err());
+}
+
+#[test]
+fn reader_seek_after_header(){
+    let mut data = b"HEADER".to_vec();
+    data.extend(encrypted());
+    let mut inner = Cursor::new(data);
+    inner.set_position(6);
+
+    let mut reader = DeltaReader::with_offsetter(inner, PassHashOffsetter::new("seek"));
+    let mut buf = [0; 10];
+    reader.read_exact(&mut buf).unwrap();
+    assert_eq!(reader.seek(SeekFrom::Current(-5)).unwrap(), 5);
+    reader.read_exact(&mut buf).unwrap();
+    assert_eq!(buf, TEST_DATA[5..15]);
+}
+
+#[test]
+fn writer_append(){
+    let (first, second) = TEST_DATA.split_at(1234);
+
+    let mut writer = DeltaWriter::with_offsetter(Cursor::new(Vec::new()), PassHashOffsetter::new("seek"));
+    writer.write_all(first).unwrap();
+    let mut inner = writer.into_inner();
+    inner.set_position(0);
+
+    let mut writer = DeltaWriter::with_offsetter(inner, PassHashOffsetter::new("seek"));
+    assert_eq!(writer.seek(SeekFrom::End(0)).unwrap(), first.len() as u64);
+    writer.write_all(second).unwrap();
+
+    assert_eq!(writer.into_inner().into_inner(), encrypted());
+}
+
+/// Accepts at most 3 bytes per write
+struct Trickle(Vec<u8>);
+
+impl Write for Trickle {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = buf.len().min(3);
+        self.0.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn writer_partial_writes(){
+    let mut writer = DeltaWriter::with_offsetter(Trickle(Vec::new()), PassHashOffsetter::new("seek"));
+    let mut rest = TEST_DATA;
+    while !rest.is_empty() {
+        let n = writer.write(rest).unwrap();
+        rest = &rest[n..];
+    }
+    assert_eq!(writer.into_inner().0, encrypted());
+}
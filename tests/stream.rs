@@ -0,0 +1,88 @@
+use std::io::{Cursor, Read, Write, ErrorKind};
+
+use delta_l::{KeystreamOffsetter, ZeroOffset};
+use delta_l::header::{self, Encoder, Decoder, Error, Kdf, Options};
+
+const TEST_DATA: &[u8] = include_bytes!("data/test_data.bin");
+
+fn encode(options: &Options) -> Vec<u8> {
+    let mut enc = Encoder::new(Vec::new(), KeystreamOffsetter::new("stream"), options).unwrap();
+    for chunk in TEST_DATA.chunks(1000) {
+        enc.write_all(chunk).unwrap();
+    }
+    enc.finish().unwrap()
+}
+
+#[test]
+fn encoder_round_trip(){
+    for &(checksum, authenticate) in &[(true, false), (false, false), (true, true), (false, true)] {
+        let encrypted = encode(&Options::new().checksum(checksum).authenticate(authenticate));
+
+        let mut dec = Vec::new();
+        delta_l::decode(KeystreamOffsetter::new("stream"), &mut &*encrypted, &mut dec).unwrap();
+        assert_eq!(dec, TEST_DATA);
+
+        let mut dec = Vec::new();
+        Decoder::new(&*encrypted, KeystreamOffsetter::new("stream")).read_to_end(&mut dec).unwrap();
+        assert_eq!(dec, TEST_DATA);
+    }
+}
+
+#[test]
+fn passphrase_round_trip(){
+    let mut enc = Encoder::with_passphrase(Vec::new(), "stream", Kdf::Keystream{rounds: 10}, &Options::new().authenticate(true)).unwrap();
+    enc.write_all(TEST_DATA).unwrap();
+    let encrypted = enc.finish().unwrap();
+
+    let mut dec = Vec::new();
+    Decoder::with_passphrase(&*encrypted, "stream").read_to_end(&mut dec).unwrap();
+    assert_eq!(dec, TEST_DATA);
+
+    let err = Decoder::new(&*encrypted, ZeroOffset).read_to_end(&mut Vec::new()).unwrap_err();
+    match err.into_inner().and_then(|e| e.downcast::<Error>().ok()).map(|e| *e) {
+        Some(Error::PassphraseRequired) => (),
+        r => panic!("expected PassphraseRequired, got {:?}", r),
+    }
+}
+
+#[test]
+fn decoder_reads_seekable_formats(){
+    let mut encrypted = Cursor::new(Vec::new());
+    delta_l::encode_with_checksum(KeystreamOffsetter::new("stream"), &mut &*TEST_DATA, &mut encrypted).unwrap();
+
+    let mut dec = Vec::new();
+    Decoder::new(&*encrypted.into_inner(), KeystreamOffsetter::new("stream")).read_to_end(&mut dec).unwrap();
+    assert_eq!(dec, TEST_DATA);
+}
+
+#[test]
+fn mismatch_at_end(){
+    let encrypted = encode(&Options::new());
+
+    let mut dec = Vec::new();
+    let err = Decoder::new(&*encrypted, ZeroOffset).read_to_end(&mut dec).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    match err.into_inner().and_then(|e| e.downcast::<Error>().ok()).map(|e| *e) {
+        Some(Error::ChecksumMismatch) => (),
+        r => panic!("expected ChecksumMismatch, got {:?}", r),
+    }
+    assert_eq!(dec.len(), TEST_DATA.len());
+
+    let encrypted = encode(&Options::new().authenticate(true));
+    let err = Decoder::new(&*encrypted, KeystreamOffsetter::new("wrong")).read_to_end(&mut Vec::new()).unwrap_err();
+    match err.into_inner().and_then(|e| e.downcast::<Error>().ok()).map(|e| *e) {
+        Some(Error::AuthenticationFailed) => (),
+        r => panic!("expected AuthenticationFailed, got {:?}", r),
+    }
+}
+
+#[test]
+fn empty(){
+    let encrypted = Encoder::new(Vec::new(), ZeroOffset, &Options::new()).unwrap().finish().unwrap();
+
+    let mut dec = Vec::new();
+    header::decode(ZeroOffset, &mut &*encrypted, &mut dec).unwrap();
+    assert!(dec.is_empty());
+    Decoder::new(&*encrypted, ZeroOffset).read_to_end(&mut dec).unwrap();
+    assert!(dec.is_empty());
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use std::io::Cursor;
+
+use delta_l::{Delta, Word, KeystreamOffsetter, ZeroOffset};
+use delta_l::header::{self, Options};
+use common::encoded;
+
+const TEST_DATA: &[u8] = include_bytes!("data/test_data.bin");
+
+#[test]
+fn known_vectors(){
+    // Two RGB pixels, each colour is chained to the same colour of the pixel before
+    let mut buf = [10, 20, 30, 11, 21, 31];
+    Delta::new().with_stride(3).encode(&mut buf, &mut ZeroOffset);
+    assert_eq!(buf, [10, 20, 30, 21, 41, 61]);
+
+    // Stereo samples of 16 bits
+    let mut buf = [0xFF, 0, 1, 0, 1, 0, 2, 0];
+    Delta::new().with_word(Word::U16Le).with_stride(2).encode(&mut buf, &mut ZeroOffset);
+    assert_eq!(buf, [0xFF, 0, 1, 0, 0, 1, 3, 0]);
+}
+
+#[test]
+fn channels_are_separate(){
+    // A change in the second channel only reaches the next words of that channel an order apart
+    let mut changed = TEST_DATA.to_vec();
+    changed[1] ^= 0xFF;
+    let delta = Delta::with_order(2).with_stride(3);
+    let before = encoded(delta, TEST_DATA, KeystreamOffsetter::new("stride"));
+    let after = encoded(delta, &changed, KeystreamOffsetter::new("stride"));
+    let differing: Vec<usize> = (0..before.len()).filter(|&i| before[i] != after[i]).collect();
+    assert_eq!(differing, [1, 4, 7]);
+}
+
+#[test]
+fn stride_one_is_plain(){
+    assert_eq!(encoded(Delta::new().with_stride(1), TEST_DATA, KeystreamOffsetter::new("stride")), encoded(Delta::new(), TEST_DATA, KeystreamOffsetter::new("stride")));
+
+    let mut plain = Cursor::new(Vec::new());
+    header::encode_v2(KeystreamOffsetter::new("stride"), &Options::new(), &mut &*TEST_DATA, &mut plain).unwrap();
+    let mut stride_one = Cursor::new(Vec::new());
+    header::encode_v2(KeystreamOffsetter::new("stride"), &Options::new().delta(Delta::new().with_stride(1)), &mut &*TEST_DATA, &mut stride_one).unwrap();
+    assert_eq!(stride_one.into_inner(), plain.into_inner());
+}
+
+#[test]
+#[should_panic]
+fn stride_zero(){
+    Delta::new().with_stride(0);
+}
@@ -0,0 +1,85 @@
+use std::io::Cursor;
+
+use delta_l::{KeystreamOffsetter, ZeroOffset};
+use delta_l::header::{self, Error, Kdf, Options};
+
+const TEST_DATA: &[u8] = include_bytes!("data/test_data.bin");
+
+fn options() -> Vec<Options> {
+    vec![
+        Options::new(),
+        Options::new().checksum(false),
+        Options::new().authenticate(true),
+        Options::new().checksum(false).authenticate(true),
+    ]
+}
+
+#[test]
+fn streaming_round_trip(){
+    for options in options() {
+        // A `Vec` isn't seekable
+        let mut encrypted = Vec::new();
+        header::encode_v2_streaming(KeystreamOffsetter::new("trailer"), &options, &mut &*TEST_DATA, &mut encrypted).unwrap();
+
+        let mut dec = Vec::new();
+        delta_l::decode(KeystreamOffsetter::new("trailer"), &mut &*encrypted, &mut dec).unwrap();
+        assert_eq!(dec, TEST_DATA);
+
+        let mut dec = Vec::new();
+        delta_l::decode_verified(KeystreamOffsetter::new("trailer"), &mut Cursor::new(&encrypted), &mut dec).unwrap();
+        assert_eq!(dec, TEST_DATA);
+    }
+}
+
+#[test]
+fn streaming_passphrase_round_trip(){
+    for options in options() {
+        let mut encrypted = Vec::new();
+        header::encode_v2_streaming_with_passphrase("trailer", Kdf::Keystream{rounds: 10}, &options, &mut &*TEST_DATA, &mut encrypted).unwrap();
+
+        let mut dec = Vec::new();
+        delta_l::decode_with_passphrase("trailer", &mut &*encrypted, &mut dec).unwrap();
+        assert_eq!(dec, TEST_DATA);
+
+        let mut dec = Vec::new();
+        header::decode_verified_with_passphrase("trailer", &mut Cursor::new(&encrypted), &mut dec).unwrap();
+        assert_eq!(dec, TEST_DATA);
+    }
+}
+
+#[test]
+fn trailer_is_checked(){
+    let mut encrypted = Vec::new();
+    header::encode_v2_streaming(KeystreamOffsetter::new("trailer"), &Options::new(), &mut &*TEST_DATA, &mut encrypted).unwrap();
+
+    match delta_l::decode(ZeroOffset, &mut &*encrypted, &mut Vec::new()) {
+        Err(Error::ChecksumMismatch) => (),
+        r => panic!("expected ChecksumMismatch, got {:?}", r),
+    }
+    match delta_l::decode_verified(ZeroOffset, &mut Cursor::new(&encrypted), &mut Vec::new()) {
+        Err(Error::ChecksumMismatch) => (),
+        r => panic!("expected ChecksumMismatch, got {:?}", r),
+    }
+
+    // Cutting into the trailer
+    let truncated = &encrypted[..encrypted.len() - 1];
+    match delta_l::decode(KeystreamOffsetter::new("trailer"), &mut &*truncated, &mut Vec::new()) {
+        Err(Error::ChecksumMismatch) => (),
+        r => panic!("expected ChecksumMismatch, got {:?}", r),
+    }
+}
+
+#[test]
+fn empty_payload(){
+    let mut encrypted = Vec::new();
+    header::encode_v2_streaming(ZeroOffset, &Options::new(), &mut &b""[..], &mut encrypted).unwrap();
+
+    let mut dec = Vec::new();
+    delta_l::decode(ZeroOffset, &mut &*encrypted, &mut dec).unwrap();
+    assert!(dec.is_empty());
+
+    match delta_l::decode(ZeroOffset, &mut &encrypted[..encrypted.len() - 1], &mut Vec::new()) {
+        Err(Error::Io(_)) => (),
+        r => panic!("expected an io error, got {:?}", r),
+    }
+}
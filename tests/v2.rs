@@ -0,0 +1,175 @@
+use std::io::Cursor;
+
+use delta_l::{PassHashOffsetter, KeystreamOffsetter, ZeroOffset};
+use delta_l::header::{self, Error, Kdf, Options};
+
+const TEST_DATA: &[u8] = include_bytes!("data/test_data.bin");
+
+fn encode_v2(options: &Options) -> Vec<u8> {
+    let mut encrypted = Cursor::new(Vec::new());
+    header::encode_v2(PassHashOffsetter::new("v2"), options, &mut &*TEST_DATA, &mut encrypted).unwrap();
+    encrypted.into_inner()
+}
+
+#[test]
+fn offsetter_round_trip(){
+    for &checksum in &[true, false] {
+        let options = Options::new().checksum(checksum).extension(0x80, b"application data".to_vec());
+        let encrypted = encode_v2(&options);
+        assert_eq!(&encrypted[..4], b"\xCE\x94V\n");
+
+        let mut dec = Vec::new();
+        delta_l::decode(PassHashOffsetter::new("v2"), &mut &*encrypted, &mut dec).unwrap();
+        assert_eq!(dec, TEST_DATA);
+
+        let mut dec = Vec::new();
+        delta_l::decode_with_passphrase("v2", &mut &*encrypted, &mut dec).unwrap();
+        assert_eq!(dec, TEST_DATA);
+    }
+}
+
+#[test]
+fn checksum_is_checked(){
+    let encrypted = encode_v2(&Options::new());
+
+    match delta_l::decode(ZeroOffset, &mut &*encrypted, &mut Vec::new()) {
+        Err(Error::ChecksumMismatch) => (),
+        r => panic!("expected ChecksumMismatch, got {:?}", r),
+    }
+}
+
+#[test]
+fn passphrase_round_trip(){
+    for &kdf in &[Kdf::PassHash{rounds: 10}, Kdf::Keystream{rounds: 10}] {
+        let mut encrypted = Cursor::new(Vec::new());
+        header::encode_v2_with_passphrase("v2", kdf, &Options::new(), &mut &*TEST_DATA, &mut encrypted).unwrap();
+        let encrypted = encrypted.into_inner();
+
+        let mut dec = Vec::new();
+        delta_l::decode_with_passphrase("v2", &mut &*encrypted, &mut dec).unwrap();
+        assert_eq!(dec, TEST_DATA);
+
+        match delta_l::decode(ZeroOffset, &mut &*encrypted, &mut Vec::new()) {
+            Err(Error::PassphraseRequired) => (),
+            r => panic!("expected PassphraseRequired, got {:?}", r),
+        }
+    }
+}
+
+#[test]
+fn unsupported(){
+    let mut encrypted = encode_v2(&Options::new());
+    // Unknown flag
+    encrypted[7] = 0x80;
+    match delta_l::decode(ZeroOffset, &mut &*encrypted, &mut Vec::new()) {
+        Err(Error::Unsupported) => (),
+        r => panic!("expected Unsupported, got {:?}", r),
+    }
+
+    // Newer version
+    encrypted[4] = 3;
+    match delta_l::decode(ZeroOffset, &mut &*encrypted, &mut Vec::new()) {
+        Err(Error::Unsupported) => (),
+        r => panic!("expected Unsupported, got {:?}", r),
+    }
+}
+
+fn encode_authenticated(kdf: Kdf, checksum: bool) -> Vec<u8> {
+    let options = Options::new().checksum(checksum).authenticate(true);
+    let mut encrypted = Cursor::new(Vec::new());
+    header::encode_v2_with_passphrase("v2", kdf, &options, &mut &*TEST_DATA, &mut encrypted).unwrap();
+    encrypted.into_inner()
+}
+
+#[test]
+fn authenticated_round_trip(){
+    for &kdf in &[Kdf::Keystream{rounds: 10}, Kdf::PassHash{rounds: 10}] {
+        for &checksum in &[true, false] {
+            let encrypted = encode_authenticated(kdf, checksum);
+
+            let mut dec = Vec::new();
+            delta_l::decode_with_passphrase("v2", &mut &*encrypted, &mut dec).unwrap();
+            assert_eq!(dec, TEST_DATA);
+        }
+    }
+
+    let mut encrypted = Cursor::new(Vec::new());
+    header::encode_v2(KeystreamOffsetter::new("v2"), &Options::new().authenticate(true), &mut &*TEST_DATA, &mut encrypted).unwrap();
+    let mut dec = Vec::new();
+    delta_l::decode(KeystreamOffsetter::new("v2"), &mut &**encrypted.get_ref(), &mut dec).unwrap();
+    assert_eq!(dec, TEST_DATA);
+}
+
+#[test]
+fn authenticate_needs_key(){
+    // Anyone who knows some of the offsets could make the tags of these
+    let options = Options::new().authenticate(true);
+    match header::encode_v2(PassHashOffsetter::new("v2"), &options, &mut &*TEST_DATA, &mut Cursor::new(Vec::new())) {
+        Err(Error::Unsupported) => (),
+        r => panic!("expected Unsupported, got {:?}", r),
+    }
+    match header::encode_v2_streaming(ZeroOffset, &options, &mut &*TEST_DATA, &mut Vec::new()) {
+        Err(Error::Unsupported) => (),
+        r => panic!("expected Unsupported, got {:?}", r),
+    }
+
+    let mut encrypted = Cursor::new(Vec::new());
+    header::encode_v2(KeystreamOffsetter::new("v2"), &options, &mut &*TEST_DATA, &mut encrypted).unwrap();
+    match delta_l::decode(ZeroOffset, &mut &**encrypted.get_ref(), &mut Vec::new()) {
+        Err(Error::Unsupported) => (),
+        r => panic!("expected Unsupported, got {:?}", r),
+    }
+}
+
+#[test]
+fn authentication_fails(){
+    let encrypted = encode_authenticated(Kdf::Keystream{rounds: 10}, false);
+    let len = encrypted.len();
+
+    // Changed ciphertext, changed salt and wrong passphrase
+    for &(index, passphrase) in &[(len - 10, "v2"), (10, "v2"), (0, "v3")] {
+        let mut encrypted = encrypted.clone();
+        if passphrase == "v2" {
+            encrypted[index] ^= 1;
+        }
+
+        let mut dec = Vec::new();
+        match delta_l::decode_with_passphrase(passphrase, &mut &*encrypted, &mut dec) {
+            Err(Error::AuthenticationFailed) => (),
+            r => panic!("expected AuthenticationFailed, got {:?}", r),
+        }
+        assert!(dec.is_empty());
+    }
+}
+
+#[test]
+fn key_check(){
+    let options = Options::new().checksum(false).key_check(true);
+    let mut encrypted = Cursor::new(Vec::new());
+    header::encode_v2_with_passphrase("v2", Kdf::PassHash{rounds: 10}, &options, &mut &*TEST_DATA, &mut encrypted).unwrap();
+    let encrypted = encrypted.into_inner();
+
+    let mut dec = Vec::new();
+    delta_l::decode_with_passphrase("v2", &mut &*encrypted, &mut dec).unwrap();
+    assert_eq!(dec, TEST_DATA);
+
+    let mut dec = Vec::new();
+    match delta_l::decode_with_passphrase("v3", &mut &*encrypted, &mut dec) {
+        Err(Error::WrongPassphrase) => (),
+        r => panic!("expected WrongPassphrase, got {:?}", r),
+    }
+    assert!(dec.is_empty());
+
+    let mut encrypted = Cursor::new(Vec::new());
+    header::encode_v2(KeystreamOffsetter::new("v2"), &options, &mut &*TEST_DATA, &mut encrypted).unwrap();
+    match delta_l::decode(KeystreamOffsetter::new("v3"), &mut &**encrypted.get_ref(), &mut Vec::new()) {
+        Err(Error::WrongPassphrase) => (),
+        r => panic!("expected WrongPassphrase, got {:?}", r),
+    }
+
+    // Offsetters without a key have no check value
+    assert!(header::read_header(&mut &*encode_v2(&options)).unwrap().extensions.is_empty());
+    let mut encrypted = Cursor::new(Vec::new());
+    header::encode_v2(ZeroOffset, &options, &mut &*TEST_DATA, &mut encrypted).unwrap();
+    assert!(header::read_header(&mut &**encrypted.get_ref()).unwrap().extensions.is_empty());
+}
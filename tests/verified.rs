@@ -0,0 +1,49 @@
+use std::io::Cursor;
+
+use delta_l::{PassHashOffsetter, KeystreamOffsetter};
+use delta_l::header::{self, Error, Kdf, Options};
+
+const TEST_DATA: &[u8] = include_bytes!("data/test_data.bin");
+const TEST_DATA_DELTA_PASS: &[u8] = include_bytes!("data/test_data.bin.delta-pass");
+
+#[test]
+fn nothing_written_on_mismatch(){
+    let mut dec = Vec::new();
+    match delta_l::decode_verified(PassHashOffsetter::new("WRONG"), &mut Cursor::new(TEST_DATA_DELTA_PASS), &mut dec) {
+        Err(Error::ChecksumMismatch) => (),
+        r => panic!("expected ChecksumMismatch, got {:?}", r),
+    }
+    assert!(dec.is_empty());
+
+    delta_l::decode_verified(PassHashOffsetter::new("SECRET"), &mut Cursor::new(TEST_DATA_DELTA_PASS), &mut dec).unwrap();
+    assert_eq!(dec, TEST_DATA);
+}
+
+#[test]
+fn verified_authenticated(){
+    let mut encrypted = Cursor::new(Vec::new());
+    let options = Options::new().authenticate(true);
+    header::encode_v2_with_passphrase("verified", Kdf::Keystream{rounds: 10}, &options, &mut &*TEST_DATA, &mut encrypted).unwrap();
+
+    let mut dec = Vec::new();
+    header::decode_verified_with_passphrase("verified", &mut Cursor::new(encrypted.get_ref()), &mut dec).unwrap();
+    assert_eq!(dec, TEST_DATA);
+
+    let mut dec = Vec::new();
+    match header::decode_verified_with_passphrase("wrong", &mut Cursor::new(encrypted.get_ref()), &mut dec) {
+        Err(Error::AuthenticationFailed) => (),
+        r => panic!("expected AuthenticationFailed, got {:?}", r),
+    }
+    assert!(dec.is_empty());
+}
+
+#[test]
+fn verified_no_checksum(){
+    let mut encrypted = Cursor::new(Vec::new());
+    header::encode_v2(KeystreamOffsetter::new("verified"), &Options::new().checksum(false), &mut &*TEST_DATA, &mut encrypted).unwrap();
+    encrypted.set_position(0);
+
+    let mut dec = Vec::new();
+    delta_l::decode_verified(KeystreamOffsetter::new("verified"), &mut encrypted, &mut dec).unwrap();
+    assert_eq!(dec, TEST_DATA);
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use std::io::{Cursor, Write, Seek, SeekFrom};
+
+use delta_l::{Delta, Word, DeltaWriter, DeltaReader, KeystreamOffsetter, ZeroOffset};
+use common::encoded;
+
+const TEST_DATA: &[u8] = include_bytes!("data/test_data.bin");
+
+#[test]
+fn known_vectors(){
+    let mut buf = [1, 0, 0xFF, 0, 1, 1];
+    Delta::new().with_word(Word::U16Le).encode(&mut buf, &mut ZeroOffset);
+    // 1, 255 + 1 and 257 + 255, with the carries going into the high bytes
+    assert_eq!(buf, [1, 0, 0, 1, 0, 2]);
+
+    let mut buf = [0, 1, 0, 0xFF, 1, 1];
+    Delta::new().with_word(Word::U16Be).encode(&mut buf, &mut ZeroOffset);
+    assert_eq!(buf, [0, 1, 1, 0, 2, 0]);
+
+    // The last 2 bytes don't make a whole word, so they're encoded a byte at a time from the start
+    let mut buf = [1, 2, 3, 4, 5, 6];
+    Delta::new().with_word(Word::U32Le).encode(&mut buf, &mut ZeroOffset);
+    assert_eq!(buf, [1, 2, 3, 4, 5, 11]);
+
+    let mut buf = [0xFF, 0xFF, 0, 0, 1, 0, 0, 0];
+    Delta::new().with_word(Word::U32Le).decode(&mut buf, &mut ZeroOffset);
+    assert_eq!(buf, [0xFF, 0xFF, 0, 0, 2, 0, 0xFF, 0xFF]);
+}
+
+#[test]
+fn reader_seek_past_end(){
+    let delta = Delta::new().with_word(Word::U64Be);
+    let data = &TEST_DATA[..2045];
+    let mut reader = DeltaReader::with_delta(Cursor::new(encoded(delta, data, KeystreamOffsetter::new("word"))), KeystreamOffsetter::new("word"), delta);
+
+    // Seeking reads whole words, so the tail is found by running into the end
+    assert_eq!(reader.seek(SeekFrom::Start(2045)).unwrap(), 2045);
+    assert!(reader.seek(SeekFrom::Start(2046)).is_err());
+}
+
+#[test]
+fn writer_seek_whole_words(){
+    let delta = Delta::new().with_word(Word::U16Le);
+    let (first, second) = TEST_DATA.split_at(1000);
+
+    let mut writer = DeltaWriter::with_delta(Cursor::new(Vec::new()), KeystreamOffsetter::new("word"), delta);
+    writer.write_all(first).unwrap();
+    let mut inner = writer.finish().unwrap();
+    inner.set_position(0);
+
+    let mut writer = DeltaWriter::with_delta(inner, KeystreamOffsetter::new("word"), delta);
+    assert!(writer.seek(SeekFrom::Start(501)).is_err());
+    assert_eq!(writer.seek(SeekFrom::End(0)).unwrap(), 1000);
+    writer.write_all(second).unwrap();
+
+    assert_eq!(writer.finish().unwrap().into_inner(), encoded(delta, TEST_DATA, KeystreamOffsetter::new("word")));
+}
@@ -0,0 +1,36 @@
+mod common;
+
+use delta_l::{Delta, Word, Combine, Offset, KeystreamOffsetter, ZeroOffset};
+use common::encoded;
+
+const TEST_DATA: &[u8] = include_bytes!("data/test_data.bin");
+
+fn xor() -> Delta {
+    Delta::new().with_combine(Combine::Xor)
+}
+
+#[test]
+fn known_vectors(){
+    let mut buf = [1, 2, 3, 0xFF];
+    xor().encode(&mut buf, &mut ZeroOffset);
+    assert_eq!(buf, [1, 3, 1, 0xFC]);
+
+    // Unlike addition, there are no carries between the bytes of a word
+    let mut buf = [0xFF, 0, 1, 0];
+    xor().with_word(Word::U16Le).encode(&mut buf, &mut ZeroOffset);
+    assert_eq!(buf, [0xFF, 0, 0xFE, 0]);
+}
+
+#[test]
+fn matches_definition(){
+    let mut offsetter = KeystreamOffsetter::new("xor");
+    let mut prev = 0;
+    let expected: Vec<u8> = TEST_DATA.iter().map(|&p| {
+        let c = p ^ offsetter.next_offset() ^ prev;
+        prev = p;
+        c
+    }).collect();
+
+    assert_eq!(encoded(xor(), TEST_DATA, KeystreamOffsetter::new("xor")), expected);
+    assert_ne!(encoded(Delta::new(), TEST_DATA, KeystreamOffsetter::new("xor")), expected);
+}